@@ -85,17 +85,279 @@ pub struct BitmapGlyph {
     /// 
     /// None for whitespace characters.
     pub bitmap_source: Option<SourceRect>,
+    /// The bounding boxes of the glyph rendered at the additional subpixel offsets `1/n, 2/n, ...` of a pixel to the right,
+    /// where `n` is the number of [`subpixel_positions`](BitmapFont::subpixel_positions) of the font.
+    /// 
+    /// These share their left edge with the [`bitmap_source`](BitmapGlyph::bitmap_source), relative to the glyph origin.
+    /// Empty unless the font was rasterized at multiple subpixel positions.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub subpixel_sources: Vec<SourceRect>,
     /// The horizontal offset that the origin of the next glyph should be from the origin of this glyph.
     pub advance_width: f32,
     /// The horizontal offset between the origin of this glyph and the leftmost point of the glyph.
     pub left_side_bearing: f32,
     /// The vertical offset between the origin of this glyph and the baseline. Typhically positive.
     pub ascent: f32,
+    /// Metrics for top-to-bottom layout.
+    /// 
+    /// None if the source font has no vertical metrics.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub vertical_metrics: Option<VerticalMetrics>,
+    /// Whether the glyph was rendered in full color, in which case it should not be tinted with the text color.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub color: bool,
+}
+
+/// Metrics of a glyph required for top-to-bottom text layout.
+/// 
+/// In vertical layout, glyphs are positioned by their vertical origin, which is typically centered horizontally
+/// at the top of the glyph's em box.
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub struct VerticalMetrics {
+    /// The vertical offset that the vertical origin of the next glyph should be below that of this glyph.
+    pub advance_height: f32,
+    /// The horizontal offset from the regular origin of this glyph to its vertical origin. Typically positive.
+    pub origin_x: f32,
+    /// The vertical offset from the regular origin of this glyph to its vertical origin. Typically positive.
+    pub origin_y: f32,
+}
+
+/// Class-based kerning information, which is much more compact than a flat table for fonts that kern
+/// entire groups of glyphs against each other.
+/// 
+/// Each character is assigned a class for when it appears on the left side of a pair, and one for when it
+/// appears on the right side. The kerning between two characters is then given by the entry for their classes
+/// in a matrix of adjustments. Class 0 is reserved for characters that do not appear in the respective map.
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub struct KerningClasses {
+    /// Map of unicode codepoints to their class when appearing on the left side of a pair.
+    pub left_classes: HashMap<char, u16>,
+    /// Map of unicode codepoints to their class when appearing on the right side of a pair.
+    pub right_classes: HashMap<char, u16>,
+    /// The number of right-side classes, including class 0.
+    pub right_class_count: u16,
+    /// Row-major matrix of kerning adjustments, indexed by `left_class * right_class_count + right_class`.
+    pub adjustments: Vec<f32>,
+}
+
+impl KerningClasses {
+    /// Returns the kerning adjustment for a pair of characters.
+    #[must_use]
+    pub fn get(&self, left: char, right: char) -> f32 {
+        let left_class = self.left_classes.get(&left).copied().unwrap_or(0);
+        let right_class = self.right_classes.get(&right).copied().unwrap_or(0);
+        let index = usize::from(left_class) * usize::from(self.right_class_count) + usize::from(right_class);
+        self.adjustments.get(index).copied().unwrap_or(0.0)
+    }
+}
+
+/// A color channel of an RGBA image.
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub enum Channel {
+    /// The first channel.
+    Red,
+    /// The second channel.
+    Green,
+    /// The third channel.
+    Blue,
+    /// The fourth channel.
+    Alpha,
+}
+
+/// The order of the color channels of an image rasterized with subpixel anti-aliasing, from left to right.
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub enum SubpixelOrder {
+    /// Red, green, blue, as on most LCD screens.
+    Rgb,
+    /// Blue, green, red.
+    Bgr,
+}
+
+/// The encoding of signed distances to glyph edges in the values of a signed distance field.
+/// 
+/// Distances are normalized by the range on their side of the edge, squared depending on the [`mapping`](DistanceField::mapping),
+/// and then mapped linearly to the values between `edge_value` and 0 outside of glyphs, or between `edge_value` and 255 inside.
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub struct DistanceField {
+    /// The distance in pixels outside of glyph edges that maps to a value of 0.
+    pub outside_range: f32,
+    /// The distance in pixels inside of glyph edges that maps to a value of 255.
+    pub inside_range: f32,
+    /// The value at glyph edges, typically 127.5.
+    pub edge_value: f32,
+    /// How distances are mapped to values.
+    pub mapping: DistanceMapping,
+    /// The type of values in the bitmap.
+    pub format: DistanceFormat,
+}
+
+impl DistanceField {
+    /// Returns the signed distance in pixels encoded by a value of an 8-bit signed distance field, which is positive inside of glyphs.
+    #[must_use]
+    pub fn distance(&self, value: u8) -> f32 {
+        self.decode(f32::from(value))
+    }
+
+    /// Like [`DistanceField::distance`], but for a value of a 16-bit signed distance field.
+    #[must_use]
+    pub fn distance_u16(&self, value: u16) -> f32 {
+        self.decode(f32::from(value) / 257.0)
+    }
+
+    fn decode(&self, value: f32) -> f32 {
+        let (fraction, range, sign) = if value < self.edge_value {
+            ((self.edge_value - value) / self.edge_value, self.outside_range, -1.0)
+        } else if value > self.edge_value {
+            ((value - self.edge_value) / (255.0 - self.edge_value), self.inside_range, 1.0)
+        } else {
+            return 0.0;
+        };
+
+        match self.mapping {
+            DistanceMapping::Linear => sign * fraction * range,
+            DistanceMapping::Squared => sign * fraction.sqrt() * range,
+        }
+    }
+}
+
+/// The type of values of a signed distance field.
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub enum DistanceFormat {
+    /// 8-bit values, see [`DistanceField::distance`].
+    U8,
+    /// 16-bit values, which are the 8-bit values scaled by 257, see [`DistanceField::distance_u16`].
+    U16,
+    /// Signed distances in pixels, clamped to the ranges but not otherwise mapped.
+    /// 
+    /// Stored in all color channels of an EXR image, or as raw little-endian floats in rows from top to bottom.
+    F32,
+}
+
+/// How distances are mapped to the values of a signed distance field, see [`DistanceField`].
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub enum DistanceMapping {
+    /// Values are proportional to the distance.
+    Linear,
+    /// Values are proportional to the squared distance, which spends more precision close to glyph edges.
+    Squared,
+}
+
+/// A drop shadow rasterized along with the glyphs, see [`BitmapFont::drop_shadow`].
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub struct DropShadow {
+    /// The offset of the shadow to the right in pixels.
+    pub offset_x: i32,
+    /// The offset of the shadow downwards in pixels.
+    pub offset_y: i32,
+    /// The radius in pixels that the shadow was blurred by.
+    pub blur_radius: f32,
+}
+
+/// The layout of the pixels of an embedded [`Bitmap`], each of which is stored in row-major order
+/// with its channels in the given order, and multi-byte channels in little-endian byte order.
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub enum PixelFormat {
+    /// A single 8-bit channel.
+    Luma8,
+    /// An 8-bit luminance channel followed by an 8-bit alpha channel.
+    LumaAlpha8,
+    /// Three 8-bit color channels.
+    Rgb8,
+    /// Three 8-bit color channels followed by an 8-bit alpha channel.
+    Rgba8,
+    /// A single 16-bit channel.
+    Luma16,
+    /// A single 32-bit floating-point channel.
+    Luma32Float,
+}
+
+impl PixelFormat {
+    /// Returns the number of bytes that each pixel takes up.
+    #[must_use]
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Luma8 => 1,
+            PixelFormat::LumaAlpha8 | PixelFormat::Luma16 => 2,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgba8 | PixelFormat::Luma32Float => 4,
+        }
+    }
+}
+
+/// The pixels of the bitmap a font was rasterized into, embedded into its metadata, see [`BitmapFont::bitmap`].
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
+#[cfg_attr(feature = "rkyv-serialize", derive(rkyv::Serialize))]
+#[cfg_attr(feature = "rkyv-deserialize", derive(rkyv::Deserialize))]
+pub struct Bitmap {
+    /// Horizontal extent in pixels.
+    pub width: u32,
+    /// Vertical extent in pixels.
+    pub height: u32,
+    /// The layout of each pixel in `data`.
+    pub format: PixelFormat,
+    /// The raw pixel data, without any padding between rows.
+    pub data: Vec<u8>,
+}
+
+impl Bitmap {
+    /// Returns the raw pixel data along with the width and height of the bitmap, ready for upload to the GPU.
+    #[must_use]
+    pub fn pixels(&self) -> (&[u8], u32, u32) {
+        (&self.data, self.width, self.height)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl ArchivedBitmap {
+    /// Returns the raw pixel data along with the width and height of the bitmap, without copying
+    /// or deserializing any of it.
+    #[must_use]
+    pub fn pixels(&self) -> (&[u8], u32, u32) {
+        (&self.data, self.width, self.height)
+    }
 }
 
 /// Runtime representation of all metadata for a single bitmap font.
 /// 
-/// Does not own or even reference the bitmap itself.
+/// Does not own or even reference the bitmap itself, unless it was [embedded](BitmapFont::bitmap).
 #[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 #[cfg_attr(feature = "serde-deserialize", derive(serde::Deserialize))]
 #[cfg_attr(feature = "rkyv", derive(rkyv::Archive))]
@@ -104,8 +366,25 @@ pub struct BitmapGlyph {
 pub struct BitmapFont {
     /// Map of unicode codepoints to glyphs in the font.
     pub glyphs: HashMap<char, BitmapGlyph>,
+    /// Map of character sequences to the ligature glyphs that replace them during [layout](BitmapFont::layout).
+    /// 
+    /// None if no ligatures were rasterized.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub ligatures: Option<HashMap<String, BitmapGlyph>>,
+    /// Map of glyph IDs in the source font to glyphs, for rendering the output of a shaping engine.
+    /// 
+    /// None if no text was shaped during rasterization. See [`BitmapFont::shape`] for usage with `rustybuzz`.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub glyph_ids: Option<HashMap<u32, BitmapGlyph>>,
     /// Additional kerning to apply as well as that given by [`BitmapGlyph`] metrics to a pair of glyphs.
+    /// 
+    /// Use [`BitmapFont::kerning`] to look up kerning regardless of representation.
     pub kerning_table: Option<HashMap<(char, char), f32>>,
+    /// Class-based alternative to the [`kerning_table`](BitmapFont::kerning_table).
+    /// 
+    /// Use [`BitmapFont::kerning`] to look up kerning regardless of representation.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub kerning_classes: Option<KerningClasses>,
     /// The highest point that any glyph in the font extends above the baseline. Typically positive.
     pub ascent: f32,
     /// The lowest point that any glyph in the font extends below the baseline. Typically negative.
@@ -114,6 +393,460 @@ pub struct BitmapFont {
     /// 
     /// This is of course only a guideline given by the font's designers.
     pub line_gap: f32,
-    /// The distance from the true pixel bounding box of any given glyph to the bounding box given by [`BitmapGlyph.bitmap_source`](BitmapGlyph).
+    /// The size of the em square of the font in pixels, which relates font units to pixels.
+    /// 
+    /// 0 in metadata written by versions of `font2img` that predate this field.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub pixels_per_em: f32,
+    /// The distance from the true pixel bounding box of any given glyph to the bounding box given by [`BitmapGlyph.bitmap_source`](BitmapGlyph),
+    /// i.e. the number of pixels to subtract from each side of the source rect when positioning it.
+    /// 
+    /// Any spacing between the glyphs in the bitmap lies outside of their source rects, and needs no consideration.
     pub padding: u32,
+    /// The number of evenly spaced horizontal subpixel offsets that each glyph was rasterized at, see [`BitmapGlyph::subpixel_sources`].
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub subpixel_positions: u8,
+    /// The number of mipmap levels below the bitmap that glyphs were laid out for, by aligning their source rects to multiples
+    /// of `2^mip_levels` pixels and keeping them at least as far apart. Sampling beyond this level blends neighboring glyphs.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub mip_levels: u8,
+    /// The width in pixels of the glyph outlines rasterized into the alpha channel of the bitmap, whose
+    /// luminance channel holds the glyphs themselves, or into the green channel if there is a
+    /// [`drop_shadow`](BitmapFont::drop_shadow). The [`SourceRect`] of each glyph covers its outline.
+    /// 
+    /// None if no outlines were rasterized.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub stroke_width: Option<f32>,
+    /// How the bitmap encodes signed distances to glyph edges.
+    /// 
+    /// None if the font was rasterized based on coverage rather than as a signed distance field.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub distance_field: Option<DistanceField>,
+    /// The channel of the RGBA bitmap that the glyphs were rasterized into, which it shares with up to three other fonts.
+    /// 
+    /// None if the font has the bitmap to itself.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub channel: Option<Channel>,
+    /// The drop shadow rasterized into the blue channel of the bitmap, in which case its red channel holds the
+    /// glyphs themselves, its green channel their outlines and its alpha channel the union of all three.
+    /// The [`SourceRect`] of each glyph covers all of these.
+    /// 
+    /// None unless rasterized with effects.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub drop_shadow: Option<DropShadow>,
+    /// Map of variation axis tags (e.g. `"wght"`) to the user-space coordinates of the rasterized instance.
+    /// 
+    /// None if the source font is not a variable font.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub variations: Option<HashMap<String, f32>>,
+    /// The subpixel order of the screen the bitmap was rasterized for, in which case its color channels hold the
+    /// coverage of the respective subpixels, and it must be blended per channel.
+    /// 
+    /// None unless rasterized with subpixel anti-aliasing.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub subpixel_order: Option<SubpixelOrder>,
+    /// The bitmap that the glyphs were rasterized into, so that the font can be shipped as a single file.
+    /// 
+    /// None if the bitmap was written to a separate image file.
+    #[cfg_attr(feature = "serde-deserialize", serde(default))]
+    pub bitmap: Option<Bitmap>,
+}
+
+impl BitmapFont {
+    /// Returns the kerning to apply between a pair of glyphs as well as that given by [`BitmapGlyph`] metrics.
+    /// 
+    /// This looks up the [`kerning_table`](BitmapFont::kerning_table) or the [`kerning_classes`](BitmapFont::kerning_classes),
+    /// whichever is present, and returns 0 if there is no kerning information.
+    #[must_use]
+    pub fn kerning(&self, left: char, right: char) -> f32 {
+        if let Some(kerning_table) = &self.kerning_table {
+            kerning_table.get(&(left, right)).copied().unwrap_or(0.0)
+        } else if let Some(kerning_classes) = &self.kerning_classes {
+            kerning_classes.get(left, right)
+        } else {
+            0.0
+        }
+    }
+
+    /// Lays out a single line of text starting at horizontal position 0, substituting [`ligatures`](BitmapFont::ligatures)
+    /// for the longest matching character sequences and applying [`kerning`](BitmapFont::kerning) between glyphs.
+    /// 
+    /// Characters without a glyph in the font are skipped.
+    #[must_use]
+    pub fn layout<'a>(&'a self, text: &'a str) -> Layout<'a> {
+        let max_ligature_len = self.ligatures.iter()
+            .flat_map(HashMap::keys)
+            .map(|sequence| sequence.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        Layout { font: self, text, max_ligature_len, rtl: false, vertical: false, x: 0.0, y: 0.0, previous: None }
+    }
+
+    /// Lays out a single column of text from top to bottom, with the vertical origin of the first glyph at position 0.
+    /// 
+    /// Glyphs are positioned by their [`vertical_metrics`](BitmapGlyph::vertical_metrics), or centered horizontally below
+    /// each other using the font's [`ascent`](BitmapFont::ascent) and [`descent`](BitmapFont::descent) if there are none.
+    /// Ligatures are substituted as in [`BitmapFont::layout`], but no kerning is applied.
+    #[must_use]
+    pub fn layout_vertical<'a>(&'a self, text: &'a str) -> Layout<'a> {
+        Layout { vertical: true, ..self.layout(text) }
+    }
+
+    /// Lays out a single line of bidirectional text like [`BitmapFont::layout`], but reorders it into visual order
+    /// per the Unicode Bidirectional Algorithm first, mirroring characters such as brackets in right-to-left runs.
+    /// 
+    /// The returned glyphs are in visual order, i.e. from left to right.
+    #[cfg(feature = "bidi")]
+    #[cfg_attr(docs_rs, doc(cfg(feature = "bidi")))]
+    #[must_use]
+    pub fn layout_bidi<'a>(&'a self, text: &'a str) -> Vec<PositionedGlyph<'a>> {
+        let bidi_info = unicode_bidi::BidiInfo::new(text, None);
+        let mut layout = self.layout(text);
+        let mut glyphs = vec![];
+        for paragraph in &bidi_info.paragraphs {
+            let (levels, runs) = bidi_info.visual_runs(paragraph, paragraph.range.clone());
+            for run in runs {
+                layout.rtl = levels[run.start].is_rtl();
+                layout.text = &text[run];
+                // Glyphs on either side of a run boundary need not be adjacent in logical order
+                layout.previous = None;
+
+                // Ligatures are matched in logical order, so reverse right-to-left runs only afterwards
+                let mut clusters = std::iter::from_fn(|| layout.next_cluster()).collect::<Vec<_>>();
+                if layout.rtl {
+                    clusters.reverse();
+                }
+
+                glyphs.extend(clusters.into_iter().map(|cluster| layout.place(cluster)));
+            }
+        }
+
+        glyphs
+    }
+
+    /// Shapes a single line of text with `rustybuzz` and positions the resulting [`glyph_ids`](BitmapFont::glyph_ids)
+    /// starting at horizontal position 0.
+    /// 
+    /// The `face` must be created from the source font, with the same [`variations`](BitmapFont::variations) applied.
+    /// Glyphs that were not rasterized are skipped, but still advance the pen.
+    #[cfg(feature = "rustybuzz")]
+    #[cfg_attr(docs_rs, doc(cfg(feature = "rustybuzz")))]
+    #[must_use]
+    pub fn shape<'a>(&'a self, face: &rustybuzz::Face, features: &[rustybuzz::Feature], text: &'a str) -> Vec<PositionedGlyph<'a>> {
+        let Some(glyph_ids) = &self.glyph_ids else {
+            return vec![];
+        };
+
+        let mut buffer = rustybuzz::UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.guess_segment_properties();
+        let output = rustybuzz::shape(face, features, buffer);
+
+        // Clusters are byte offsets into the text, in visual rather than logical order for right-to-left runs
+        let mut cluster_starts = output.glyph_infos().iter().map(|info| info.cluster as usize).collect::<Vec<_>>();
+        cluster_starts.sort_unstable();
+        cluster_starts.dedup();
+
+        #[allow(clippy::cast_precision_loss)]
+        let units_to_pixels = self.pixels_per_em / face.units_per_em() as f32;
+        let (mut pen_x, mut pen_y) = (0, 0);
+        let mut glyphs = vec![];
+        for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+            if let Some(glyph) = glyph_ids.get(&info.glyph_id) {
+                let start = info.cluster as usize;
+                let end = cluster_starts.iter().copied().find(|&cluster| cluster > start).unwrap_or(text.len());
+
+                #[allow(clippy::cast_precision_loss)]
+                let (x, bitmap_source) = self.subpixel_source(glyph, (pen_x + position.x_offset) as f32 * units_to_pixels);
+                #[allow(clippy::cast_precision_loss)]
+                glyphs.push(PositionedGlyph {
+                    glyph,
+                    bitmap_source,
+                    x,
+                    y: (pen_y + position.y_offset) as f32 * units_to_pixels,
+                    text: &text[start..end],
+                });
+            }
+
+            pen_x += position.x_advance;
+            pen_y += position.y_advance;
+        }
+
+        glyphs
+    }
+
+    /// Selects the subpixel variant of a glyph that is closest to the given horizontal position,
+    /// returning the position rounded to whole pixels accordingly and the bounding box of the variant.
+    fn subpixel_source<'a>(&self, glyph: &'a BitmapGlyph, x: f32) -> (f32, Option<&'a SourceRect>) {
+        if glyph.subpixel_sources.is_empty() {
+            return (x, glyph.bitmap_source.as_ref());
+        }
+
+        let pixel = x.floor();
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let variant = ((x - pixel) * f32::from(self.subpixel_positions)).round() as usize;
+        match variant.checked_sub(1).map(|index| glyph.subpixel_sources.get(index)) {
+            None => (pixel, glyph.bitmap_source.as_ref()),
+            Some(Some(subpixel_source)) => (pixel, Some(subpixel_source)),
+            // Rounded up to the next pixel
+            Some(None) => (pixel + 1.0, glyph.bitmap_source.as_ref()),
+        }
+    }
+}
+
+/// A glyph positioned by [`BitmapFont::layout`] or one of its variants.
+pub struct PositionedGlyph<'a> {
+    /// The glyph to draw.
+    pub glyph: &'a BitmapGlyph,
+    /// The bounding box of the glyph in the bitmap, which is one of its [`subpixel_sources`](BitmapGlyph::subpixel_sources)
+    /// if the glyph was rasterized at multiple subpixel positions, and the [`bitmap_source`](BitmapGlyph::bitmap_source) otherwise.
+    pub bitmap_source: Option<&'a SourceRect>,
+    /// The horizontal position of the glyph's origin.
+    /// 
+    /// When a subpixel variant is selected, this is rounded to whole pixels, since the fractional part is already accounted for.
+    pub x: f32,
+    /// The vertical position of the glyph's origin relative to the baseline, or the top of the column in
+    /// [vertical layout](BitmapFont::layout_vertical). Positive values are above the baseline.
+    pub y: f32,
+    /// The characters of the laid out text represented by this glyph, more than one in case of a ligature.
+    pub text: &'a str,
+}
+
+/// Iterator over the [`PositionedGlyph`]s of a line of text, created by [`BitmapFont::layout`].
+pub struct Layout<'a> {
+    font: &'a BitmapFont,
+    text: &'a str,
+    max_ligature_len: usize,
+    rtl: bool,
+    vertical: bool,
+    x: f32,
+    y: f32,
+    previous: Option<char>,
+}
+
+/// A glyph that represents one or more characters, along with the characters on its left and right edge for kerning.
+#[derive(Clone, Copy)]
+struct Cluster<'a> {
+    text: &'a str,
+    glyph: &'a BitmapGlyph,
+    left: char,
+    right: char,
+}
+
+impl<'a> Layout<'a> {
+    /// Returns the horizontal position of the origin of the next glyph, i.e. the width of the text laid out so far.
+    #[must_use]
+    pub fn x(&self) -> f32 {
+        self.x
+    }
+
+    /// Returns the vertical position of the origin of the next glyph, i.e. the negated height of the text laid out so far
+    /// in [vertical layout](BitmapFont::layout_vertical).
+    #[must_use]
+    pub fn y(&self) -> f32 {
+        self.y
+    }
+
+    /// Finds the longest ligature at the start of the remaining text.
+    fn next_ligature(&self) -> Option<(&'a str, &'a BitmapGlyph)> {
+        let ligatures = self.font.ligatures.as_ref()?;
+        let text = self.text;
+        let ends = text.char_indices()
+            .map(|(index, character)| index + character.len_utf8())
+            .take(self.max_ligature_len)
+            .skip(1)
+            .collect::<Vec<_>>();
+        ends.into_iter()
+            .rev()
+            .find_map(|len| ligatures.get(&text[..len]).map(|glyph| (&text[..len], glyph)))
+    }
+
+    /// Splits the next glyph off the remaining text, skipping characters without a glyph.
+    fn next_cluster(&mut self) -> Option<Cluster<'a>> {
+        loop {
+            let first = self.text.chars().next()?;
+            if let Some((text, glyph)) = self.next_ligature() {
+                self.text = &self.text[text.len()..];
+
+                let last = text.chars().next_back().unwrap_or(first);
+                let (left, right) = if self.rtl { (last, first) } else { (first, last) };
+                return Some(Cluster { text, glyph, left, right });
+            }
+
+            let (text, rest) = self.text.split_at(first.len_utf8());
+            self.text = rest;
+
+            #[cfg(feature = "bidi")]
+            let first = if self.rtl { unicode_bidi_mirroring::get_mirrored(first).unwrap_or(first) } else { first };
+
+            if let Some(glyph) = self.font.glyphs.get(&first) {
+                return Some(Cluster { text, glyph, left: first, right: first });
+            }
+        }
+    }
+
+    /// Positions a glyph to the right of, or below the previous one.
+    fn place(&mut self, cluster: Cluster<'a>) -> PositionedGlyph<'a> {
+        if self.vertical {
+            let (advance_height, origin_x, origin_y) = match &cluster.glyph.vertical_metrics {
+                Some(metrics) => (metrics.advance_height, metrics.origin_x, metrics.origin_y),
+                None => (self.font.ascent - self.font.descent, cluster.glyph.advance_width / 2.0, self.font.ascent),
+            };
+
+            let y = self.y;
+            self.y -= advance_height;
+            return PositionedGlyph {
+                glyph: cluster.glyph,
+                bitmap_source: cluster.glyph.bitmap_source.as_ref(),
+                x: -origin_x,
+                y: y - origin_y,
+                text: cluster.text,
+            };
+        }
+
+        // Kerning pairs are in logical order, which is the reverse of the visual order in right-to-left runs
+        if let Some(previous) = self.previous {
+            let (left, right) = if self.rtl { (cluster.left, previous) } else { (previous, cluster.left) };
+            self.x += self.font.kerning(left, right);
+        }
+        self.previous = Some(cluster.right);
+
+        let (x, bitmap_source) = self.font.subpixel_source(cluster.glyph, self.x);
+        self.x += cluster.glyph.advance_width;
+        PositionedGlyph { glyph: cluster.glyph, bitmap_source, x, y: 0.0, text: cluster.text }
+    }
+}
+
+impl<'a> Iterator for Layout<'a> {
+    type Item = PositionedGlyph<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cluster = self.next_cluster()?;
+        Some(self.place(cluster))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(advance_width: f32) -> BitmapGlyph {
+        BitmapGlyph {
+            bitmap_source: None,
+            subpixel_sources: vec![],
+            advance_width,
+            left_side_bearing: 0.0,
+            ascent: 0.0,
+            vertical_metrics: None,
+            color: false,
+        }
+    }
+
+    fn font(glyphs: HashMap<char, BitmapGlyph>, kerning_table: HashMap<(char, char), f32>) -> BitmapFont {
+        BitmapFont {
+            glyphs,
+            ligatures: None,
+            glyph_ids: None,
+            kerning_table: Some(kerning_table),
+            kerning_classes: None,
+            ascent: 10.0,
+            descent: -2.0,
+            line_gap: 0.0,
+            pixels_per_em: 12.0,
+            padding: 0,
+            subpixel_positions: 1,
+            mip_levels: 0,
+            stroke_width: None,
+            distance_field: None,
+            channel: None,
+            drop_shadow: None,
+            variations: None,
+            subpixel_order: None,
+            bitmap: None,
+        }
+    }
+
+    #[test]
+    fn layout_prefers_longest_ligature() {
+        let mut font = font(HashMap::from([('f', glyph(5.0)), ('i', glyph(3.0)), ('l', glyph(3.0))]), HashMap::new());
+        font.ligatures = Some(HashMap::from([
+            ("ff".to_owned(), glyph(9.0)),
+            ("fi".to_owned(), glyph(7.0)),
+            ("ffi".to_owned(), glyph(11.0)),
+        ]));
+
+        let texts = font.layout("ffiflff").map(|glyph| glyph.text).collect::<Vec<_>>();
+        assert_eq!(texts, ["ffi", "f", "l", "ff"]);
+    }
+
+    #[test]
+    fn subpixel_source_picks_nearest_variant() {
+        let rect = |x| SourceRect { x, y: 0, width: NonZeroU8::new(4).unwrap(), height: NonZeroU8::new(4).unwrap() };
+        let mut font = font(HashMap::new(), HashMap::new());
+        font.subpixel_positions = 3;
+        let glyph = BitmapGlyph { bitmap_source: Some(rect(0)), subpixel_sources: vec![rect(10), rect(20)], ..glyph(5.0) };
+
+        let selections = [2.1, 2.4, 2.6, 2.9].map(|x| {
+            let (x, source) = font.subpixel_source(&glyph, x);
+            (x, source.map(|source| source.x))
+        });
+
+        // Offsets closer to the next pixel than to the last variant round up to the unshifted glyph
+        assert_eq!(selections, [(2.0, Some(0)), (2.0, Some(10)), (2.0, Some(20)), (3.0, Some(0))]);
+    }
+
+    #[cfg(feature = "serde-deserialize")]
+    #[test]
+    fn deserialize_metadata_without_later_fields() {
+        const METADATA: &str = "(
+            glyphs: {
+                'A': (
+                    bitmap_source: Some((x: 0, y: 0, width: 20, height: 24)),
+                    advance_width: 16.0,
+                    left_side_bearing: -4.0,
+                    ascent: 21.0,
+                ),
+            },
+            kerning_table: None,
+            ascent: 18.0,
+            descent: -5.0,
+            line_gap: 0.0,
+            padding: 8,
+        )";
+
+        let font: BitmapFont = ron::from_str(METADATA).unwrap();
+        assert!(font.glyphs[&'A'].subpixel_sources.is_empty());
+        assert!(!font.glyphs[&'A'].color);
+        assert!(font.ligatures.is_none() && font.distance_field.is_none() && font.bitmap.is_none());
+        assert_eq!((font.subpixel_positions, font.mip_levels), (0, 0));
+    }
+
+    #[cfg(feature = "bidi")]
+    #[test]
+    fn layout_bidi_kerns_right_to_left_pairs_in_logical_order() {
+        // Alef followed by bet is kerned, but not bet followed by alef
+        let font = font(
+            HashMap::from([('\u{5D0}', glyph(7.0)), ('\u{5D1}', glyph(6.0))]),
+            HashMap::from([(('\u{5D0}', '\u{5D1}'), -2.0)]),
+        );
+
+        let glyphs = font.layout_bidi("\u{5D0}\u{5D1}");
+        let positions = glyphs.iter().map(|glyph| (glyph.text, glyph.x)).collect::<Vec<_>>();
+        assert_eq!(positions, [("\u{5D1}", 0.0), ("\u{5D0}", 4.0)]);
+    }
+
+    #[cfg(feature = "bidi")]
+    #[test]
+    fn layout_bidi_does_not_kern_across_runs() {
+        // Bet follows the space visually, but alef does logically
+        let font = font(
+            HashMap::from([('a', glyph(5.0)), (' ', glyph(3.0)), ('\u{5D0}', glyph(7.0)), ('\u{5D1}', glyph(6.0))]),
+            HashMap::from([(('\u{5D1}', ' '), -2.0), ((' ', '\u{5D1}'), -2.0)]),
+        );
+
+        let glyphs = font.layout_bidi("a \u{5D0}\u{5D1}");
+        let positions = glyphs.iter().map(|glyph| (glyph.text, glyph.x)).collect::<Vec<_>>();
+        assert_eq!(positions, [("a", 0.0), (" ", 5.0), ("\u{5D1}", 8.0), ("\u{5D0}", 14.0)]);
+    }
 }
@@ -1,20 +1,36 @@
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::num::NonZeroU8;
 
-use clap::Parser;
-use rusttype::Font;
+use ab_glyph_rasterizer::{point, Point, Rasterizer};
+use clap::{Parser, ValueEnum};
+use skrifa::bitmap::{BitmapData, BitmapFormat, BitmapStrikes, Origin};
+use skrifa::instance::{Location, Size};
+use skrifa::outline::{DrawSettings, Engine, HintingInstance, HintingOptions, OutlinePen, SmoothMode, Target};
+use skrifa::prelude::*;
+use skrifa::raw::tables::colr::Colr;
+use skrifa::raw::tables::gpos::{Gpos, PairPos, PositionSubtables, ValueContext};
+use skrifa::raw::tables::gsub::{Gsub, SubstitutionSubtables};
+use skrifa::raw::tables::kern::{Kern, SubtableKind};
+use skrifa::raw::tables::layout::FeatureList;
+use skrifa::raw::TableProvider;
+use skrifa::OutlineGlyphCollection;
 
 use raster_fonts::*;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(override_usage = "font2img [OPTIONS] <FONT_PATH> <IMG_PATH> <META_PATH> [CHARSET]...\n       font2img [OPTIONS] --embed-bitmap <FONT_PATH> <META_PATH> [CHARSET]...")]
+#[command(group(clap::ArgGroup::new("stroke_target").args(["coverage_levels", "effects"]).multiple(true)))]
 struct Args {
     /// Path to the font file to convert
     font_path: String,
-    /// Path to where the output image should be written
-    img_path: String,
+    /// Path to where the output image should be written, omitted with --embed-bitmap
+    #[arg(required_unless_present = "embed_bitmap")]
+    img_path: Option<String>,
     /// Path to where the output meta data should be written.
-    meta_path: String,
+    #[arg(required_unless_present = "embed_bitmap")]
+    meta_path: Option<String>,
     /// List of Unicode codepoint ranges written in hex
     charset: Vec<String>,
     /// Enable coverage-based (as opposed to SDF) rasterization with the specified number of distinct levels above 0
@@ -23,46 +39,340 @@ struct Args {
     /// Desired font pixel height
     #[arg(short, long, default_value_t = 24.0)]
     scale: f32,
-    /// Desired number of pixels between glyphs in output texture
-    #[arg(short, long, default_value_t = 8)]
-    padding: u32,
+    /// Enable coverage-based rasterization with subpixel anti-aliasing for LCD screens with the given subpixel order, writing an RGB image
+    #[arg(long, value_enum, conflicts_with = "color")]
+    subpixel: Option<Subpixel>,
+    /// Additionally rasterize outlines of the given width in pixels around glyphs into the alpha channel of the output image
+    #[arg(long, requires = "stroke_target", conflicts_with_all = ["color", "subpixel"])]
+    stroke: Option<f32>,
+    /// Write an RGBA image with glyphs in the red channel, their --stroke outlines in green, a drop shadow in blue and the union of all in alpha
+    #[arg(long, requires = "stroke", conflicts_with_all = ["color", "subpixel"])]
+    effects: bool,
+    /// Offset in pixels of the drop shadow written with --effects, to the right and down
+    #[arg(long, requires = "effects", default_value = "2,2", value_parser = parse_offset, allow_hyphen_values = true)]
+    shadow_offset: (i32, i32),
+    /// Blur radius in pixels of the drop shadow written with --effects
+    #[arg(long, requires = "effects", default_value_t = 2.0)]
+    shadow_blur: f32,
+    /// Number of evenly spaced horizontal subpixel offsets to rasterize each glyph at, e.g. 3 for offsets of 0, 1/3 and 2/3 pixels
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    subpixel_positions: u8,
+    /// Embolden outlines by the given stroke width in pixels, for fonts without a bold face, e.g. 1/24 of the scale
+    #[arg(long)]
+    synthetic_bold: Option<f32>,
+    /// Slant outlines to the right by the given angle in degrees, for fonts without an italic face, e.g. 12
+    #[arg(long)]
+    synthetic_oblique: Option<f32>,
+    /// Grid-fit outlines to the pixel grid, which keeps small text sharp
+    #[arg(long, value_enum)]
+    hinting: Option<Hinting>,
+    /// Distance in pixels from glyph edges at which SDF values saturate, outwards and optionally inwards, e.g. 8 or 8,4, which must not exceed the --padding [default: padding]
+    #[arg(long, value_parser = parse_range, conflicts_with_all = ["coverage_levels", "subpixel"])]
+    sdf_range: Option<(f32, f32)>,
+    /// How distances within the --sdf-range are mapped to SDF values
+    #[arg(long, value_enum, default_value_t = SdfMapping::Squared)]
+    sdf_mapping: SdfMapping,
+    /// SDF value at glyph edges, with distances outside mapped to values below it and inside above it
+    #[arg(long, default_value_t = 127.5)]
+    sdf_edge_value: f32,
+    /// Precision of SDF values, where f32 writes signed distances in pixels to OpenEXR, KTX2 and DDS images, or raw little-endian floats to .raw and .bin files
+    #[arg(long, value_enum, default_value_t = SdfFormat::U8, conflicts_with_all = ["coverage_levels", "subpixel", "effects", "color"])]
+    sdf_format: SdfFormat,
+    /// Compute SDFs from glyphs rasterized at this many times the resolution, for more accurate edges
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=16), conflicts_with_all = ["coverage_levels", "subpixel"])]
+    sdf_supersample: u32,
+    /// Write glyphs into a single channel of an RGBA image, keeping the other channels of an existing image at the same path
+    #[arg(long, value_enum, conflicts_with_all = ["color", "subpixel", "stroke", "effects"])]
+    channel: Option<ImageChannel>,
+    /// Number of pixels around each glyph within its bounds, for distance fields and effects to spread into [default: 8, or the --sdf-range if wider]
+    #[arg(short, long)]
+    padding: Option<u32>,
+    /// Number of empty pixels between the bounds of glyphs in output texture, which keeps filtering from bleeding into neighbors
+    #[arg(long, default_value_t = 0)]
+    spacing: u32,
+    /// Align the bounds of glyphs to multiples of 2^N pixels and space them at least as far apart, so that the first N levels of mipmaps don't bleed between glyphs
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=7))]
+    mip_levels: u8,
+    /// Additionally write each of the --mip-levels next to the output image, with _mip1, _mip2, ... appended to its name, or into the same file for KTX2 and DDS images
+    #[arg(long, requires = "mip_levels")]
+    write_mips: bool,
+    /// Compress KTX2 and DDS images with BC4 if they have a single channel, or with BC5 if they have two
+    #[arg(long, conflicts_with_all = ["color", "effects", "subpixel"])]
+    block_compression: bool,
+    /// Embed the bitmap into the meta data instead of writing an output image, whose path is then omitted
+    #[arg(long, conflicts_with_all = ["channel", "write_mips", "block_compression"])]
+    embed_bitmap: bool,
     /// Side length of the square output texture
     #[arg(short, long, default_value_t = 512)]
     output_image_size: u32,
     /// Exclude additional kerning information from output metadata
     #[arg(long)]
     skip_kerning_table: bool,
+    /// Always rasterize outlines, even if the font has an embedded bitmap strike at the pixels per em that --scale works out to
+    #[arg(long)]
+    skip_embedded_bitmaps: bool,
+    /// Comma-separated list of variation axis values to rasterize, e.g. wght=650,wdth=90
+    #[arg(long, value_delimiter = ',')]
+    variations: Vec<String>,
+    /// Additionally rasterize standard ligatures (liga and clig) that can be formed from the charset, whereas contextual alternates (calt) such as => in programming fonts require --shape-text
+    #[arg(long)]
+    ligatures: bool,
+    /// Text files to shape line by line, additionally rasterizing every resulting glyph by glyph ID
+    #[arg(long)]
+    shape_text: Vec<String>,
+    /// Let different glyphs that rasterize to identical bitmaps share their bounds, as glyphs with identical IDs always do
+    #[arg(long)]
+    deduplicate_bitmaps: bool,
+    /// Rasterize color glyphs (COLR layers, sbix and CBDT bitmaps) in full color, writing an RGBA image
+    #[arg(long)]
+    color: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Hinting {
+    /// Use the hinting instructions of the font, falling back to automatic hinting for fonts without any
+    Native,
+    /// Always use automatic hinting
+    Auto,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum SdfMapping {
+    /// Proportional to the distance
+    Linear,
+    /// Proportional to the squared distance, which spends more precision close to the edge
+    Squared,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ImageChannel {
+    R,
+    G,
+    B,
+    A,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum SdfFormat {
+    U8,
+    U16,
+    F32,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Subpixel {
+    Rgb,
+    Bgr,
 }
 
 fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    // Without an output image, the meta data path and charset follow the font path directly
+    if args.embed_bitmap {
+        args.charset.splice(0..0, args.meta_path.take());
+        args.meta_path = args.img_path.take();
+    }
+
+    let Some(meta_path) = args.meta_path.clone() else {
+        eprintln!("Missing path to where the output meta data should be written.");
+        return;
+    };
 
     let current_dir = std::env::current_dir().expect("Failed to retrieve current directory");
     let font_path = current_dir.join(args.font_path);
+
+    let distance_field = args.coverage_levels.is_none() && args.subpixel.is_none();
+    // Distances are only measured within the bounds of each glyph, so these must extend as far as the --sdf-range
+    let sdf_padding = args.sdf_range.map_or(8, |(outside_range, _)| (outside_range.ceil() as u32).max(8));
+    let padding = args.padding.unwrap_or(if distance_field { sdf_padding } else { 8 });
+    if let Some((outside_range, _)) = args.sdf_range.filter(|&(outside_range, _)| outside_range > padding as f32) {
+        eprintln!("An --sdf-range of {outside_range} requires a --padding of at least {}, so that distances saturate within the bounds of each glyph.", outside_range.ceil());
+        return;
+    }
     let font_data = std::fs::read(font_path).expect("Failed to read font data");
-    let font = Font::try_from_vec(font_data).expect("Failed to parse font data");
-    let scale = rusttype::Scale::uniform(args.scale);
+    let font = FontRef::from_index(&font_data, 0).expect("Failed to parse font data");
+
+    let (location, variations) = {
+        let axes = font.axes();
+        let mut settings = vec![];
+        let mut errors = false;
+
+        for arg in args.variations.iter() {
+            let Some((tag, value)) = arg.split_once('=') else {
+                eprintln!("Error parsing variation specifier: {arg}");
+                errors = true;
+                continue;
+            };
+
+            let Ok(value) = value.parse::<f32>() else {
+                eprintln!("Error parsing variation specifier: {arg}");
+                errors = true;
+                continue;
+            };
+
+            if !axes.iter().any(|axis| axis.tag() == tag) {
+                eprintln!("Font has no variation axis named '{tag}'");
+                errors = true;
+                continue;
+            }
+
+            settings.push((tag, value));
+        }
+
+        if errors {
+            eprintln!("! Valid variation specifiers are:");
+            eprintln!("    [AXIS_TAG]=[VALUE]");
+            eprintln!("Available axes in this font are:");
+            for axis in axes.iter() {
+                eprintln!("    {} ({} to {}, default {})", axis.tag(), axis.min_value(), axis.max_value(), axis.default_value());
+            }
+            return;
+        }
+
+        let variations = if axes.is_empty() {
+            None
+        } else {
+            let variations = axes.iter().map(|axis| {
+                let value = settings.iter().rev()
+                    .find(|&&(tag, _)| axis.tag() == tag)
+                    .map_or(axis.default_value(), |&(_, value)| value)
+                    .clamp(axis.min_value(), axis.max_value());
+                (axis.tag().to_string(), value)
+            });
+
+            Some(variations.collect::<HashMap<_, _>>())
+        };
+
+        (axes.location(settings), variations)
+    };
+
+    // Scale such that the distance from ascent to descent matches the desired pixel height
+    let size = {
+        let metrics = font.metrics(Size::unscaled(), &location);
+        Size::new(args.scale * metrics.units_per_em as f32 / (metrics.ascent - metrics.descent))
+    };
+
+    // Pixel fonts often come with hand-tuned bitmaps for specific sizes, which are preferable to any rasterization,
+    // unless the outlines need to be transformed into a synthetic style. Strikes are sized in pixels per em rather
+    // than by the pixel height that --scale refers to, so they are matched against the derived size instead.
+    let synthetic_style = args.synthetic_bold.is_some() || args.synthetic_oblique.is_some();
+    let embedded_strike = BitmapStrikes::with_format(&font, BitmapFormat::Ebdt)
+        .filter(|_| !args.skip_embedded_bitmaps && !synthetic_style)
+        .and_then(|strikes| strikes.iter().find(|strike| size.ppem().is_some_and(|ppem| (strike.ppem() - ppem).abs() < 0.5)));
+
+    // All other metrics and glyphs must then agree with the size of the strike
+    let size = embedded_strike.as_ref().map_or(size, |strike| Size::new(strike.ppem()));
+
+    let charmap = font.charmap();
+    let outlines = font.outline_glyphs();
+    let glyph_metrics = font.glyph_metrics(size, &location);
+
+    let units_to_pixels = size.linear_scale(font.head().expect("Failed to read font header").units_per_em());
+
+    let img_extension = std::path::Path::new(args.img_path.as_deref().unwrap_or_default()).extension().and_then(|os_str| os_str.to_str()).unwrap_or_default();
+    let texture_container = img_extension.eq_ignore_ascii_case("ktx2") || img_extension.eq_ignore_ascii_case("dds");
+    let raw_image = img_extension.eq_ignore_ascii_case("raw") || img_extension.eq_ignore_ascii_case("bin");
+    let float_image = texture_container || raw_image || img_extension.eq_ignore_ascii_case("exr");
+    if args.sdf_format == SdfFormat::F32 && !float_image && !args.embed_bitmap {
+        eprintln!("Floating-point distance fields can only be written to EXR, KTX2 and DDS images, or as raw floats to .raw or .bin files.");
+        return;
+    }
+
+    if raw_image && args.sdf_format != SdfFormat::F32 {
+        eprintln!("Only floating-point distance fields can be written as raw floats.");
+        return;
+    }
+    if args.block_compression && !texture_container {
+        eprintln!("Block compression is only supported for KTX2 and DDS images.");
+        return;
+    }
+
+    if args.block_compression && args.sdf_format != SdfFormat::U8 {
+        eprintln!("Block compression is only supported for 8-bit distance fields.");
+        return;
+    }
+
+    if args.channel.is_some() && texture_container {
+        eprintln!("Cannot write into a single channel of KTX2 or DDS images, as these can't be read back.");
+        return;
+    }
 
-    let max_dist = (args.padding as f32).powi(2);
+    if args.channel.is_some() && args.sdf_format != SdfFormat::U8 {
+        eprintln!("Only 8-bit distance fields can be written into a single channel.");
+        return;
+    }
+
+    if !(0.0..=255.0).contains(&args.sdf_edge_value) {
+        eprintln!("SDF edge value must be between 0 and 255, got {}.", args.sdf_edge_value);
+        return;
+    }
+
+    // Distances saturate at the edge of the padding by default, but a wider spread may be worth a sparser atlas
+    let (outside_range, inside_range) = args.sdf_range.unwrap_or((padding as f32, padding as f32));
 
     let mut outbuf = image::ImageBuffer::<image::Luma<u8>, _>::new(args.output_image_size, args.output_image_size);
     outbuf.fill(0x00);
 
     let mut out_metadata = {
-        let rusttype::VMetrics {
-            ascent, descent, line_gap
-        } = font.v_metrics(scale);
+        let skrifa::metrics::Metrics {
+            ascent, descent, leading, ..
+        } = font.metrics(size, &location);
 
         BitmapFont {
             glyphs: HashMap::new(),
             kerning_table: None,
+            kerning_classes: None,
+            ligatures: None,
+            glyph_ids: None,
             ascent,
             descent,
-            line_gap,
-            padding: args.padding,
+            line_gap: leading,
+            pixels_per_em: size.ppem().unwrap_or_default(),
+            padding,
+            subpixel_positions: args.subpixel_positions,
+            mip_levels: args.mip_levels,
+            stroke_width: args.stroke,
+            distance_field: distance_field.then_some(DistanceField {
+                outside_range,
+                inside_range,
+                edge_value: args.sdf_edge_value,
+                mapping: match args.sdf_mapping {
+                    SdfMapping::Linear => DistanceMapping::Linear,
+                    SdfMapping::Squared => DistanceMapping::Squared,
+                },
+                format: match args.sdf_format {
+                    SdfFormat::U8 => DistanceFormat::U8,
+                    SdfFormat::U16 => DistanceFormat::U16,
+                    SdfFormat::F32 => DistanceFormat::F32,
+                },
+            }),
+            channel: args.channel.map(|channel| match channel {
+                ImageChannel::R => Channel::Red,
+                ImageChannel::G => Channel::Green,
+                ImageChannel::B => Channel::Blue,
+                ImageChannel::A => Channel::Alpha,
+            }),
+            drop_shadow: args.effects.then_some(DropShadow {
+                offset_x: args.shadow_offset.0,
+                offset_y: args.shadow_offset.1,
+                blur_radius: args.shadow_blur,
+            }),
+            variations,
+            subpixel_order: args.subpixel.map(|subpixel| match subpixel {
+                Subpixel::Rgb => SubpixelOrder::Rgb,
+                Subpixel::Bgr => SubpixelOrder::Bgr,
+            }),
+            bitmap: None,
         }
     };
 
+    // Subpixel anti-aliasing only makes sense for coverage-based rasterization
+    let coverage_levels = match args.subpixel {
+        Some(_) => Some(args.coverage_levels.unwrap_or(255)),
+        None => args.coverage_levels,
+    };
+
     let charset = {
         let mut charset = BTreeSet::<char>::new();
         let mut errors = false;
@@ -139,77 +449,338 @@ fn main() {
         charset
     };
 
+    // Several characters may map to the same glyph, e.g. U+0020 and U+00A0
+    let mut glyph_chars = HashMap::<GlyphId, Vec<char>>::new();
+    for &character in charset.iter() {
+        glyph_chars.entry(charmap.map(character).unwrap_or_default()).or_default().push(character);
+    }
+
+    let mut glyph_keys = charset.iter()
+        .map(|&character| (GlyphKey::Char(character), charmap.map(character).unwrap_or_default()))
+        .collect::<Vec<_>>();
+
+    // Contextual alternates (calt) substitute a glyph per character rather than one for the whole sequence,
+    // so there is nothing to look up by character sequence, and shaping has to take care of them
+    if args.ligatures {
+        let ligatures = font.gsub().map(|gsub| gsub_ligatures(&gsub, &["liga", "clig"], &glyph_chars)).unwrap_or_default();
+        if ligatures.is_empty() {
+            eprintln!("Font has no ligatures that can be formed from the charset.");
+        }
+
+        glyph_keys.extend(ligatures.into_iter().map(|(sequence, glyph_id)| (GlyphKey::Ligature(sequence), glyph_id)));
+    }
+
+    if !args.shape_text.is_empty() {
+        let mut face = rustybuzz::Face::from_slice(&font_data, 0).expect("Failed to parse font data");
+        if let Some(variations) = &out_metadata.variations {
+            let variations = variations.iter()
+                .map(|(tag, &value)| rustybuzz::Variation { tag: rustybuzz::ttf_parser::Tag::from_bytes_lossy(tag.as_bytes()), value })
+                .collect::<Vec<_>>();
+            face.set_variations(&variations);
+        }
+
+        let mut shaped_glyphs = BTreeSet::new();
+        for path in args.shape_text.iter() {
+            let text = std::fs::read_to_string(current_dir.join(path)).expect("Failed to read text to shape");
+            for line in text.lines() {
+                let mut buffer = rustybuzz::UnicodeBuffer::new();
+                buffer.push_str(line);
+                buffer.guess_segment_properties();
+
+                let output = rustybuzz::shape(&face, &[], buffer);
+                shaped_glyphs.extend(output.glyph_infos().iter().map(|info| info.glyph_id));
+            }
+        }
+
+        glyph_keys.extend(shaped_glyphs.into_iter().map(|glyph_id| (GlyphKey::Id(glyph_id), GlyphId::new(glyph_id))));
+    }
+
+    let vmtx = font.vmtx().ok();
+    let vorg = font.vorg().ok();
+    let vvar = font.vvar().ok();
+    let vertical_metrics = |glyph_id: GlyphId, advance_width: f32| {
+        let vmtx = vmtx.as_ref()?;
+        let coords = location.coords();
+
+        let mut advance_height = f32::from(vmtx.advance(glyph_id)?);
+        if let Some(delta) = vvar.as_ref().and_then(|vvar| vvar.advance_height_delta(glyph_id, coords).ok()) {
+            advance_height += delta.to_f32();
+        }
+
+        // Without a VORG table, the vertical origin is defined in terms of the top side bearing
+        let origin_y = if let Some(vorg) = &vorg {
+            let delta = vvar.as_ref().and_then(|vvar| vvar.v_org_delta(glyph_id, coords).ok()).unwrap_or_default();
+            (f32::from(vorg.vertical_origin_y(glyph_id)) + delta.to_f32()) * units_to_pixels
+        } else {
+            let mut top_side_bearing = f32::from(vmtx.side_bearing(glyph_id)?);
+            if let Some(delta) = vvar.as_ref().and_then(|vvar| vvar.tsb_delta(glyph_id, coords).ok()) {
+                top_side_bearing += delta.to_f32();
+            }
+
+            let y_max = glyph_metrics.bounds(glyph_id).map_or(0.0, |bounds| bounds.y_max);
+            top_side_bearing * units_to_pixels + y_max
+        };
+
+        Some(VerticalMetrics { advance_height: advance_height * units_to_pixels, origin_x: advance_width / 2.0, origin_y })
+    };
+
+    let color_tables = ColorTables::new(&font);
+    let load_color_glyph = |glyph_id| {
+        if args.color { ColorGlyph::load(&color_tables, &outlines, glyph_id, size, &location, units_to_pixels) } else { None }
+    };
+
+    let embedded_bitmap = |glyph_id| embedded_strike.as_ref().and_then(|strike| strike.get(glyph_id));
+    let hinting_instance = args.hinting.map(|hinting| {
+        let engine = match hinting {
+            Hinting::Native => Engine::AutoFallback,
+            Hinting::Auto => Engine::Auto(None),
+        };
+
+        // Binary images call for the strongest hinting
+        let target = if args.subpixel.is_some() {
+            Target::Smooth { mode: SmoothMode::Lcd, symmetric_rendering: true, preserve_linear_metrics: false }
+        } else if coverage_levels == Some(1) {
+            Target::Mono
+        } else {
+            Target::default()
+        };
+        HintingInstance::new(&outlines, size, &location, HintingOptions { engine, target }).expect("Failed to set up hinting")
+    });
+
+    let load_outline = |glyph_id| {
+        let draw_settings = match &hinting_instance {
+            Some(instance) => DrawSettings::hinted(instance, false),
+            None => DrawSettings::unhinted(size, &location),
+        };
+
+        let mut outline = GlyphOutline::load(&outlines, glyph_id, draw_settings);
+        if let Some(strength) = args.synthetic_bold {
+            outline = outline.embolden(strength);
+        }
+        if let Some(angle) = args.synthetic_oblique {
+            outline = outline.oblique(angle);
+        }
+
+        outline
+    };
+
+    let load_glyph = |glyph_id, subpixel_offset| {
+        embedded_bitmap(glyph_id)
+            .and_then(|bitmap| Glyph::from_bitmap(&bitmap))
+            .unwrap_or_else(|| Glyph::Outline(load_outline(glyph_id).with_subpixel_offset(subpixel_offset)))
+    };
+
+    // The subpixel filter bleeds into the neighboring pixel on either side, and strokes extend beyond the glyph
+    let subpixel_margin = i32::from(args.subpixel.is_some());
+    let stroke_margin = args.stroke.map_or(0, |stroke| stroke.max(0.0).ceil() as i32);
+    let widen_bounding_box = |bounding_box: PixelRect| PixelRect {
+        min_x: bounding_box.min_x - subpixel_margin - stroke_margin,
+        min_y: bounding_box.min_y - stroke_margin,
+        max_x: bounding_box.max_x + subpixel_margin + stroke_margin,
+        max_y: bounding_box.max_y + stroke_margin,
+    };
+
+    // Coverage of every subpixel variant, which determines everything that is rendered for a monochrome glyph.
+    // It must be rasterized just like the glyph will be, since glyphs that look alike at one resolution may not at another.
+    let fingerprint = |glyph_id, subpixel_positions| {
+        let mut fingerprint = vec![];
+        for variant in 0..subpixel_positions {
+            let glyph = load_glyph(glyph_id, f32::from(variant) / f32::from(subpixel_positions));
+            let Some(bounding_box) = glyph.pixel_bounding_box() else {
+                continue;
+            };
+
+            let (width, height) = (bounding_box.width() as u32, bounding_box.height() as u32);
+            let coverage = if args.subpixel.is_some() {
+                // The subpixel filter spreads into one more pixel on either side
+                let mut coverage = vec![0; ((width + 2) * height * 3) as usize];
+                glyph.draw_subpixel(|x, y, v| {
+                    let index = ((y * (width + 2) + x) * 3) as usize;
+                    coverage[index..index + 3].copy_from_slice(&v.map(|v| (v * 255.0).round() as u8));
+                });
+                coverage
+            } else if distance_field {
+                // Distances only depend on which cells of the supersampled grid are inside the glyph
+                let supersample = args.sdf_supersample;
+                let mut inside = vec![0u8; (width * supersample * height * supersample).div_ceil(8) as usize];
+                glyph.draw_supersampled(supersample, |x, y, v| {
+                    let index = (y * width * supersample + x) as usize;
+                    if v > 0.5 {
+                        inside[index / 8] |= 1 << (index % 8);
+                    }
+                });
+                inside
+            } else {
+                let mut coverage = vec![0; (width * height) as usize];
+                glyph.draw(|x, y, v| coverage[(y * width + x) as usize] = (v * 255.0).round() as u8);
+                coverage
+            };
+
+            fingerprint.extend(bounding_box.width().to_le_bytes());
+            fingerprint.extend(bounding_box.height().to_le_bytes());
+            fingerprint.extend(coverage);
+        }
+
+        fingerprint
+    };
+
+    let mut raster_glyphs = vec![];
     let mut bounding_boxes = vec![];
-    for &glyph_id in charset.iter() {
-        let scaled_glyph = font.glyph(glyph_id).scaled(scale);
-        let rusttype::HMetrics {
-            advance_width,
-            left_side_bearing
-        } = scaled_glyph.h_metrics();
-        let glyph = scaled_glyph.positioned(rusttype::Point::default());
+    let mut packed_glyph_ids = HashMap::new();
+    let mut packed_bitmaps = HashMap::new();
+    let mut duplicates = HashMap::new();
+    for (key, glyph_id) in glyph_keys {
+        let embedded_bitmap = embedded_bitmap(glyph_id);
+        let mut advance_width = embedded_bitmap.as_ref().and_then(|bitmap| bitmap.advance)
+            .or_else(|| glyph_metrics.advance_width(glyph_id))
+            .unwrap_or_default();
+        let mut left_side_bearing = embedded_bitmap.as_ref().map(|bitmap| bitmap.inner_bearing_x)
+            .or_else(|| glyph_metrics.left_side_bearing(glyph_id))
+            .unwrap_or_default();
+        let color_glyph = load_color_glyph(glyph_id);
+        let color = color_glyph.is_some();
+
+        // Hinting and synthetic styles move the left edge of the outline, and emboldening widens the glyph
+        if (synthetic_style || hinting_instance.is_some()) && !color && embedded_bitmap.is_none() {
+            let original = GlyphOutline::load(&outlines, glyph_id, DrawSettings::unhinted(size, &location)).pixel_bounding_box();
+            let transformed = load_outline(glyph_id).pixel_bounding_box();
+            if let (Some(original), Some(transformed)) = (original, transformed) {
+                left_side_bearing += (transformed.min_x - original.min_x) as f32;
+            }
+        }
+        if synthetic_style && !color {
+            advance_width += args.synthetic_bold.unwrap_or_default();
+        }
+        let mut vertical_metrics = vertical_metrics(glyph_id, advance_width);
+        if let (Some(vertical_metrics), Some(strength), false) = (&mut vertical_metrics, args.synthetic_bold, color) {
+            vertical_metrics.advance_height += strength;
+        }
+
+        // Color glyphs and embedded bitmaps can't be shifted by fractions of a pixel
+        let subpixel_positions = if color || embedded_bitmap.is_some() { 1 } else { args.subpixel_positions };
+
+        let bounding_box = match color_glyph {
+            Some(color_glyph) => color_glyph.pixel_bounding_box(),
+            None => load_glyph(glyph_id, 0.0).pixel_bounding_box(),
+        };
 
-        let Some(bounding_box) = glyph.pixel_bounding_box() else {
-            if !glyph_id.is_whitespace() {
-                eprintln!("Failed to obtain bounding box for non-whitespace glyph {:x}", glyph_id as u32);
+        let Some(bounding_box) = bounding_box else {
+            // Shaped glyphs may well be whitespace, but there is no character to check
+            if matches!(key, GlyphKey::Char(character) if !character.is_whitespace()) || matches!(key, GlyphKey::Ligature(_)) {
+                eprintln!("Failed to obtain bounding box for non-whitespace glyph {key}");
             }
     
-            let glyph_metadata = BitmapGlyph { bitmap_source: None, advance_width, left_side_bearing, ascent: 0.0 };
-            out_metadata.glyphs.insert(glyph_id, glyph_metadata);
+            let glyph_metadata = BitmapGlyph {
+                bitmap_source: None,
+                subpixel_sources: vec![],
+                advance_width,
+                left_side_bearing,
+                ascent: 0.0,
+                vertical_metrics,
+                color,
+            };
+
+            raster_glyphs.push((key, glyph_id, glyph_metadata));
             continue;
         };
 
-        let width = bounding_box.width() as u32;
-        let padded_w = width + args.padding * 2;
+        left_side_bearing -= (subpixel_margin + stroke_margin) as f32;
 
-        let height = bounding_box.height() as u32;
-        let padded_h = height + args.padding * 2;
+        // Shifted glyphs may extend one pixel further to the right, but their bounding boxes all start at the same pixel
+        let variant_boxes = std::iter::once(bounding_box)
+            .chain((1..subpixel_positions).filter_map(|variant| {
+                load_glyph(glyph_id, f32::from(variant) / f32::from(subpixel_positions)).pixel_bounding_box()
+            }))
+            .map(widen_bounding_box)
+            .collect::<Vec<_>>();
 
-        if padded_w > 0xFF || padded_h > 0xFF {
-            eprintln!("Glyph {:x} is too large: padded to {padded_w}x{padded_h}, max is 255x255.", glyph_id as u32);
+        let padded_sizes = variant_boxes.iter()
+            .map(|bounding_box| (bounding_box.width() as u32 + padding * 2, bounding_box.height() as u32 + padding * 2))
+            .collect::<Vec<_>>();
+
+        if let Some((padded_w, padded_h)) = padded_sizes.iter().copied().find(|&(w, h)| w > 0xFF || h > 0xFF) {
+            eprintln!("Glyph {key} is too large: padded to {padded_w}x{padded_h}, max is 255x255.");
             continue;
         }
 
-        let ascent = bounding_box.min.y as f32 * -1.0;
+        let ascent = -(variant_boxes[0].min_y as f32);
+
+        let glyph_metadata = BitmapGlyph {
+            bitmap_source: None,
+            subpixel_sources: vec![],
+            advance_width,
+            left_side_bearing,
+            ascent,
+            vertical_metrics,
+            color,
+        };
+
+        // Several characters may map to the same glyph, and different glyphs may well look the same
+        let glyph_index = raster_glyphs.len();
+        let mut original = packed_glyph_ids.get(&glyph_id).copied();
+        if original.is_none() {
+            packed_glyph_ids.insert(glyph_id, glyph_index);
+            if args.deduplicate_bitmaps && !color {
+                let bitmap = fingerprint(glyph_id, subpixel_positions);
+                original = packed_bitmaps.get(&bitmap).copied();
+                packed_bitmaps.entry(bitmap).or_insert(glyph_index);
+            }
+        }
 
-        let glyph_metadata = BitmapGlyph { bitmap_source: None, advance_width, left_side_bearing, ascent };
-        out_metadata.glyphs.insert(glyph_id, glyph_metadata);
-        bounding_boxes.push((glyph_id, padded_w, padded_h));
+        if let Some(original) = original {
+            duplicates.insert(glyph_index, original);
+        } else {
+            for (variant, (padded_w, padded_h)) in padded_sizes.into_iter().enumerate() {
+                bounding_boxes.push((glyph_index, variant, padded_w, padded_h));
+            }
+        }
+        raster_glyphs.push((key, glyph_id, glyph_metadata));
     }
-    bounding_boxes.sort_by_key(|&(_, w, h)| std::cmp::Reverse(w * h));
-    'glyph_placement: for (glyph_id, w, h) in bounding_boxes.into_iter() {
-        for ty in 0..(args.output_image_size - h) {
-            'search: for tx in 0..(args.output_image_size - w) {
+
+    // Each texel of mip level N covers an aligned block of 2^N pixels, and filtering reaches into the neighboring block
+    let alignment = 1 << args.mip_levels;
+    let spacing = if args.mip_levels > 0 { args.spacing.max(alignment) } else { args.spacing };
+
+    let mut placements = vec![];
+    bounding_boxes.sort_by_key(|&(_, _, w, h)| std::cmp::Reverse(w * h));
+    'glyph_placement: for (glyph_index, variant, w, h) in bounding_boxes.into_iter() {
+        for ty in (0..(args.output_image_size - h)).step_by(alignment as usize) {
+            'search: for tx in (0..(args.output_image_size - w)).step_by(alignment as usize) {
+                // Spacing is reserved to the right and bottom of each glyph, as far as the texture extends
+                let occupied_w = (w + spacing).next_multiple_of(alignment).min(args.output_image_size - tx);
+                let occupied_h = (h + spacing).next_multiple_of(alignment).min(args.output_image_size - ty);
+
                 let valid = !(
                     outbuf.get_pixel(tx, ty).0[0] != 0 ||
-                    outbuf.get_pixel(tx + w - 1, ty).0[0] != 0 ||
-                    outbuf.get_pixel(tx, ty + h - 1).0[0] != 0 ||
-                    outbuf.get_pixel(tx + w - 1, ty + h - 1).0[0] != 0
+                    outbuf.get_pixel(tx + occupied_w - 1, ty).0[0] != 0 ||
+                    outbuf.get_pixel(tx, ty + occupied_h - 1).0[0] != 0 ||
+                    outbuf.get_pixel(tx + occupied_w - 1, ty + occupied_h - 1).0[0] != 0
                 );
 
                 if !valid { continue 'search; }
 
-                for ity in 0..h {
-                    for itx in 0..w {
+                for ity in 0..occupied_h {
+                    for itx in 0..occupied_w {
                         if outbuf.get_pixel(tx + itx, ty + ity).0[0] != 0 {
                             continue 'search;
                         }
                     }
                 }
 
-                for ity in 0..h {
-                    for itx in 0..w {
+                for ity in 0..occupied_h {
+                    for itx in 0..occupied_w {
                         outbuf.put_pixel(tx + itx, ty + ity, image::Luma([0xFF; 1]));
                     }
                 }
 
-                let bitmap_source = Some(SourceRect {
+                let bitmap_source = SourceRect {
                     x: tx as u16,
                     y: ty as u16,
                     width: NonZeroU8::new(w as u8).unwrap(),
                     height: NonZeroU8::new(h as u8).unwrap()
-                });
+                };
 
-                out_metadata.glyphs.get_mut(&glyph_id).unwrap().bitmap_source = bitmap_source;
+                placements.push((glyph_index, variant, bitmap_source));
                 continue 'glyph_placement;
             }
         }
@@ -218,18 +789,88 @@ fn main() {
         return;
     }
 
+    // Glyphs were packed by size, but subpixel variants need to be stored in order
+    placements.sort_by_key(|&(glyph_index, variant, _)| (glyph_index, variant));
+    for (glyph_index, variant, bitmap_source) in placements {
+        let glyph_metadata = &mut raster_glyphs[glyph_index].2;
+        if variant == 0 {
+            glyph_metadata.bitmap_source = Some(bitmap_source);
+        } else {
+            glyph_metadata.subpixel_sources.push(bitmap_source);
+        }
+    }
+
+    let copy = |&SourceRect { x, y, width, height }: &SourceRect| SourceRect { x, y, width, height };
+    for (&duplicate, &original) in &duplicates {
+        let BitmapGlyph { bitmap_source, subpixel_sources, .. } = &raster_glyphs[original].2;
+        let bitmap_source = bitmap_source.as_ref().map(copy);
+        let subpixel_sources = subpixel_sources.iter().map(copy).collect();
+        let glyph_metadata = &mut raster_glyphs[duplicate].2;
+        glyph_metadata.bitmap_source = bitmap_source;
+        glyph_metadata.subpixel_sources = subpixel_sources;
+    }
+
+    let subpixel_positions = f32::from(args.subpixel_positions);
+    let render_targets = raster_glyphs.iter()
+        .enumerate()
+        .filter(|(glyph_index, (_, _, glyph_metadata))| !glyph_metadata.color && !duplicates.contains_key(glyph_index))
+        .flat_map(|(_, (_, glyph_id, glyph_metadata))| {
+            glyph_metadata.bitmap_source.iter()
+                .chain(glyph_metadata.subpixel_sources.iter())
+                .enumerate()
+                .map(move |(variant, bitmap_source)| (*glyph_id, variant as f32 / subpixel_positions, bitmap_source))
+        });
+
     outbuf.fill(0x00);
-    if let Some(levels) = args.coverage_levels {
-        for (&glyph_id, glyph_metadata) in out_metadata.glyphs.iter() {
-            let Some(SourceRect { x: tx, y: ty, width: _, height: _ }) = glyph_metadata.bitmap_source else {
+    let mut subpixel_outbuf = image::RgbImage::new(outbuf.width(), outbuf.height());
+    let mut stroke_outbuf = image::GrayAlphaImage::new(outbuf.width(), outbuf.height());
+    let mut distance_outbuf = image::ImageBuffer::<image::Luma<f32>, _>::from_pixel(outbuf.width(), outbuf.height(), image::Luma([-outside_range]));
+
+    // Maps signed distances in pixels to 8-bit values, though not necessarily rounded to integers
+    let encode_distance = |signed_distance: f32| {
+        out_metadata.distance_field.as_ref().map_or(0.0, |distance_field| encode_distance(distance_field, signed_distance))
+    };
+
+    if let Some(levels) = coverage_levels {
+        for (glyph_id, subpixel_offset, &SourceRect { x: tx, y: ty, width, height }) in render_targets.clone() {
+            let glyph = load_glyph(glyph_id, subpixel_offset);
+            let quantize = |v: f32| (((v * (levels as f32)).round() / (levels as f32)) * 255.0).round() as u8;
+            if let Some(stroke) = args.stroke {
+                let width = (width.get() as u32 - padding * 2) as usize;
+                let height = (height.get() as u32 - padding * 2) as usize;
+                let margin = stroke_margin as u32;
+
+                let mut fill = vec![0.0; width * height];
+                glyph.draw(|x, y, v| fill[(y + margin) as usize * width + (x + margin) as usize] = v);
+                let outline = dilate(&fill, width, stroke);
+
+                for (index, (&fill, &outline)) in fill.iter().zip(outline.iter()).enumerate() {
+                    let x = tx as u32 + padding + (index % width) as u32;
+                    let y = ty as u32 + padding + (index / width) as u32;
+                    outbuf.put_pixel(x, y, image::Luma([quantize(fill)]));
+                    stroke_outbuf.put_pixel(x, y, image::LumaA([quantize(fill), quantize(outline)]));
+                }
                 continue;
-            };
+            }
+
+            if let Some(subpixel) = args.subpixel {
+                glyph.draw_subpixel(|x, y, [r, g, b]| {
+                    let x = tx as u32 + padding + x;
+                    let y = ty as u32 + padding + y;
+                    let subpixels = match subpixel {
+                        Subpixel::Rgb => [r, g, b],
+                        Subpixel::Bgr => [b, g, r],
+                    };
+
+                    subpixel_outbuf.put_pixel(x, y, image::Rgb(subpixels.map(quantize)));
+                });
+                continue;
+            }
 
-            let glyph = font.glyph(glyph_id).scaled(scale).positioned(rusttype::Point::default());
             glyph.draw(|x, y, v| {
-                let x = tx as u32 + args.padding + x;
-                let y = ty as u32 + args.padding + y;
-                let pixel_value = (((v * (levels as f32)).round() / (levels as f32)) * 255.0).round() as u8;
+                let x = tx as u32 + padding + x;
+                let y = ty as u32 + padding + y;
+                let pixel_value = quantize(v);
                 
                 outbuf.put_pixel(x, y, image::Luma([pixel_value; 1]));
             });
@@ -237,31 +878,31 @@ fn main() {
     } else {
         let mut outside_buf = vec![];
         let mut inside_buf = vec![];
-        for (&glyph_id, glyph_metadata) in out_metadata.glyphs.iter() {
-            let Some(SourceRect { x: tx, y: ty, width, height }) = glyph_metadata.bitmap_source else {
-                continue;
-            };
+        for (glyph_id, subpixel_offset, &SourceRect { x: tx, y: ty, width, height }) in render_targets.clone() {
+            let glyph = load_glyph(glyph_id, subpixel_offset);
 
-            let glyph = font.glyph(glyph_id).scaled(scale).positioned(rusttype::Point::default());
-
-            let padded_w = width.get() as u32;
-            let padded_h = height.get() as u32;
+            // Distances are computed on a grid of supersample x supersample cells per pixel
+            let supersample = args.sdf_supersample;
+            let padded_w = width.get() as u32 * supersample;
+            let padded_h = height.get() as u32 * supersample;
             let n_pixels = padded_w * padded_h;
 
-            let width = padded_w - args.padding * 2;
+            let width = padded_w - padding * 2 * supersample;
+            let max_dist = (padded_w * padded_w + padded_h * padded_h) as f32;
+            let margin = (padding + stroke_margin as u32) * supersample;
             
             outside_buf.clear();
             outside_buf.resize(n_pixels as usize, max_dist);
 
-            glyph.draw(|x, y, v| {
-                let idx = (((args.padding + y) * padded_w) + args.padding + x) as usize;
+            glyph.draw_supersampled(supersample, |x, y, v| {
+                let idx = (((margin + y) * padded_w) + margin + x) as usize;
                 if v <= 0.5 { outside_buf[idx] = max_dist; }
                 else { outside_buf[idx] = 0.0; }
             });
 
             // assign vertical distances column-wise
             for x in 0..width {
-                let x = x + args.padding;
+                let x = x + padding * supersample;
 
                 // propagate distances downwards
                 let mut dist_step = 1.0;
@@ -295,15 +936,15 @@ fn main() {
             inside_buf.clear();
             inside_buf.resize(n_pixels as usize, 0.0);
 
-            glyph.draw(|x, y, v| {
-                let idx = (((args.padding + y) * padded_w) + args.padding + x) as usize;
+            glyph.draw_supersampled(supersample, |x, y, v| {
+                let idx = (((margin + y) * padded_w) + margin + x) as usize;
                 if v <= 0.5 { inside_buf[idx] = 0.0; }
                 else { inside_buf[idx] = max_dist; }
             });
 
             // assign vertical distances column-wise
             for x in 0..width {
-                let x = x + args.padding;
+                let x = x + padding * supersample;
                 
                 // propagate distances downwards
                 let mut dist_step = 1.0;
@@ -335,69 +976,274 @@ fn main() {
             }
 
             // determine actual distances row-wise
-            for y in 0..padded_h {
-                for x_here in 0..padded_w {
-                    let idx_here = ((y * padded_w) + x_here) as usize;
-                    let mut dist_min = outside_buf[idx_here];
-                    for x_there in 0..padded_w {
-                        let idx_there = ((y * padded_w) + x_there) as usize;
-                        let dist = outside_buf[idx_there] + (x_there as f32 - x_here as f32).powi(2);
-                        if dist_min > dist {
-                            dist_min = dist;
-                        }
+            let to_pixels = (supersample * supersample) as f32;
+            let signed_distance_at = |x_here: u32, y: u32| {
+                let idx_here = ((y * padded_w) + x_here) as usize;
+                let mut dist_min = outside_buf[idx_here];
+                for x_there in 0..padded_w {
+                    let idx_there = ((y * padded_w) + x_there) as usize;
+                    let dist = outside_buf[idx_there] + (x_there as f32 - x_here as f32).powi(2);
+                    if dist_min > dist {
+                        dist_min = dist;
                     }
+                }
 
-                    let outside_distance = (dist_min / max_dist).clamp(0.0, 1.0);
+                let outside_distance = (dist_min / to_pixels).sqrt();
 
-                    let mut dist_min = inside_buf[idx_here];
-                    for x_there in 0..padded_w {
-                        let idx_there = ((y * padded_w) + x_there) as usize;
-                        let dist = inside_buf[idx_there] + (x_there as f32 - x_here as f32).powi(2);
-                        if dist_min > dist {
-                            dist_min = dist;
-                        }
+                let mut dist_min = inside_buf[idx_here];
+                for x_there in 0..padded_w {
+                    let idx_there = ((y * padded_w) + x_there) as usize;
+                    let dist = inside_buf[idx_there] + (x_there as f32 - x_here as f32).powi(2);
+                    if dist_min > dist {
+                        dist_min = dist;
                     }
+                }
+
+                let inside_distance = (dist_min / to_pixels).sqrt();
+                if outside_distance > 0.0 { -outside_distance } else { inside_distance }
+            };
+
+            // Pixel centers fall between the two middle cells for even supersampling factors, so average those
+            let center_cells = if supersample % 2 == 0 { supersample / 2 - 1..supersample / 2 + 1 } else { supersample / 2..supersample / 2 + 1 };
+            let center_cell_count = center_cells.len() * center_cells.len();
+            for pixel_y in 0..padded_h / supersample {
+                for pixel_x in 0..padded_w / supersample {
+                    let cells = center_cells.clone().flat_map(|cy| center_cells.clone().map(move |cx| (cx, cy)));
+                    let distance_sum = cells
+                        .map(|(cx, cy)| signed_distance_at(pixel_x * supersample + cx, pixel_y * supersample + cy))
+                        .sum::<f32>();
+                    let distance = distance_sum / center_cell_count as f32;
 
-                    let inside_distance = (dist_min / max_dist).clamp(0.0, 1.0);
-                    
-                    let signed_distance = if outside_distance > 0.0 { -outside_distance } else { inside_distance };
-                    let pixel_value = (((signed_distance + 1.0) / 2.0) * 255.0).round() as u8;
-                    outbuf.put_pixel(tx as u32 + x_here, ty as u32 + y, image::Luma([pixel_value; 1]));
+                    let signed_distance = if distance < 0.0 {
+                        distance.max(-outside_range)
+                    } else {
+                        distance.min(inside_range)
+                    };
+                    let pixel_value = encode_distance(signed_distance).round() as u8;
+                    let (x, y) = (tx as u32 + pixel_x, ty as u32 + pixel_y);
+                    outbuf.put_pixel(x, y, image::Luma([pixel_value; 1]));
+                    distance_outbuf.put_pixel(x, y, image::Luma([signed_distance]));
+                }
+            }
+        }
+    }
+
+    // Effects are rasterized across the whole padded rect, next to the fill that was rendered above
+    let mut effects_outbuf = image::RgbaImage::new(outbuf.width(), outbuf.height());
+    if args.effects {
+        let levels = f32::from(coverage_levels.unwrap_or(255));
+        let quantize = |v: f32| (((v * levels).round() / levels) * 255.0).round() as u8;
+        let (shadow_x, shadow_y) = args.shadow_offset;
+        let stroke = args.stroke.unwrap_or_default();
+
+        for (glyph_id, subpixel_offset, &SourceRect { x: tx, y: ty, width, height }) in render_targets {
+            let glyph = load_glyph(glyph_id, subpixel_offset);
+            let width = width.get() as usize;
+            let height = height.get() as usize;
+            let margin = padding + stroke_margin as u32;
+
+            let mut fill = vec![0.0; width * height];
+            glyph.draw(|x, y, v| fill[(y + margin) as usize * width + (x + margin) as usize] = v);
+            let outline = dilate(&fill, width, stroke);
+            let silhouette = fill.iter().zip(outline.iter()).map(|(&fill, &outline)| fill.max(outline)).collect::<Vec<_>>();
+            let blurred = blur(&silhouette, width, args.shadow_blur);
+
+            for y in 0..height {
+                for x in 0..width {
+                    let index = y * width + x;
+                    let (source_x, source_y) = (x as i32 - shadow_x, y as i32 - shadow_y);
+                    let shadow = if (0..width as i32).contains(&source_x) && (0..height as i32).contains(&source_y) {
+                        blurred[source_y as usize * width + source_x as usize]
+                    } else {
+                        0.0
+                    };
+
+                    let (x, y) = (tx as u32 + x as u32, ty as u32 + y as u32);
+                    let mask = silhouette[index].max(shadow);
+                    effects_outbuf.put_pixel(x, y, image::Rgba([
+                        outbuf.get_pixel(x, y).0[0],
+                        quantize(outline[index]),
+                        quantize(shadow),
+                        quantize(mask),
+                    ]));
                 }
             }
         }
     }
 
+    // Color glyphs are drawn on top, with monochrome glyphs in the alpha channel of a white background
+    let outbuf = if args.color {
+        let mut color_outbuf = image::RgbaImage::from_fn(outbuf.width(), outbuf.height(), |x, y| {
+            image::Rgba([0xFF, 0xFF, 0xFF, outbuf.get_pixel(x, y).0[0]])
+        });
+
+        for (_, glyph_id, glyph_metadata) in raster_glyphs.iter() {
+            let (Some(SourceRect { x: tx, y: ty, .. }), Some(color_glyph)) = (&glyph_metadata.bitmap_source, load_color_glyph(*glyph_id)) else {
+                continue;
+            };
+
+            color_glyph.draw(|x, y, color| {
+                color_outbuf.put_pixel(*tx as u32 + padding + x, *ty as u32 + padding + y, image::Rgba(color));
+            });
+        }
+
+        image::DynamicImage::ImageRgba8(color_outbuf)
+    } else if args.effects {
+        image::DynamicImage::ImageRgba8(effects_outbuf)
+    } else if args.subpixel.is_some() {
+        image::DynamicImage::ImageRgb8(subpixel_outbuf)
+    } else if args.stroke.is_some() {
+        image::DynamicImage::ImageLumaA8(stroke_outbuf)
+    } else if let Some(channel) = args.channel {
+        // Other fonts may have been written into the other channels already
+        let mut shared_outbuf = match image::open(current_dir.join(args.img_path.as_deref().unwrap_or_default())) {
+            Ok(image) => image.to_rgba8(),
+            Err(image::ImageError::IoError(error)) if error.kind() == std::io::ErrorKind::NotFound => {
+                image::RgbaImage::new(outbuf.width(), outbuf.height())
+            },
+            Err(error) => {
+                eprintln!("Failed to read existing output image: {error}");
+                return;
+            },
+        };
+
+        if shared_outbuf.dimensions() != outbuf.dimensions() {
+            eprintln!("Existing output image is {}x{}, but output-image-size is {}.", shared_outbuf.width(), shared_outbuf.height(), outbuf.width());
+            return;
+        }
+
+        for (shared_pixel, pixel) in shared_outbuf.pixels_mut().zip(outbuf.pixels()) {
+            shared_pixel.0[channel as usize] = pixel.0[0];
+        }
+
+        image::DynamicImage::ImageRgba8(shared_outbuf)
+    } else if args.sdf_format == SdfFormat::U16 {
+        image::DynamicImage::ImageLuma16(image::ImageBuffer::from_fn(outbuf.width(), outbuf.height(), |x, y| {
+            image::Luma([(encode_distance(distance_outbuf.get_pixel(x, y).0[0]) * 257.0).round() as u16])
+        }))
+    } else if args.sdf_format == SdfFormat::F32 {
+        // There are no single channel float images, so use all three channels
+        image::DynamicImage::ImageRgb32F(image::ImageBuffer::from_fn(outbuf.width(), outbuf.height(), |x, y| {
+            image::Rgb([distance_outbuf.get_pixel(x, y).0[0]; 3])
+        }))
+    } else {
+        image::DynamicImage::ImageLuma8(outbuf)
+    };
+
     if !args.skip_kerning_table {
+        // Like most text renderers, only fall back to the legacy kern table if there is no GPOS kerning
+        let kern = font.kern().ok();
+        let kerning_lookups = font.gpos().map(|gpos| gpos_kerning_lookups(&gpos)).unwrap_or_default();
+        let value_context = ValueContext::new()
+            .with_coords(location.coords())
+            .with_var_store(font.gdef().ok().and_then(|gdef| gdef.item_var_store()?.ok()));
+
+        let glyph_pairs = if !kerning_lookups.is_empty() {
+            gpos_kerning_pairs(&kerning_lookups, &glyph_chars, &value_context)
+        } else if let Some(kern) = kern {
+            legacy_kerning_pairs(&kern, &glyph_chars)
+        } else {
+            HashMap::new()
+        };
+
         let mut kerning_table = HashMap::new();
-        for &first in charset.iter() {
-            for &second in charset.iter() {
-                let kerning_offset = font.pair_kerning(scale, first, second);
-                if kerning_offset != 0.0 {
-                    kerning_table.insert((first, second), kerning_offset);
+        for ((first_id, second_id), kerning_offset) in glyph_pairs {
+            if kerning_offset == 0 {
+                continue;
+            }
+
+            for &first in glyph_chars[&first_id].iter() {
+                for &second in glyph_chars[&second_id].iter() {
+                    kerning_table.insert((first, second), kerning_offset as f32 * units_to_pixels);
                 }
             }
         }
 
         if !kerning_table.is_empty() {
-            out_metadata.kerning_table = Some(kerning_table);
+            // Rough estimate of the serialized size of the flat table
+            match kerning_classes(&kerning_table, kerning_table.len() * 12) {
+                Some(kerning_classes) => out_metadata.kerning_classes = Some(kerning_classes),
+                None => out_metadata.kerning_table = Some(kerning_table),
+            }
+        }
+    }
+
+    for (key, _, glyph_metadata) in raster_glyphs {
+        match key {
+            GlyphKey::Char(character) => {
+                out_metadata.glyphs.insert(character, glyph_metadata);
+            },
+            GlyphKey::Ligature(sequence) => {
+                out_metadata.ligatures.get_or_insert_with(HashMap::new).insert(sequence, glyph_metadata);
+            },
+            GlyphKey::Id(glyph_id) => {
+                out_metadata.glyph_ids.get_or_insert_with(HashMap::new).insert(glyph_id, glyph_metadata);
+            },
         }
     }
 
-    outbuf.save(args.img_path).expect("Failed to write output image");
+    if args.embed_bitmap {
+        out_metadata.bitmap = Some(embed_bitmap(outbuf));
+    } else {
+        let img_path = current_dir.join(args.img_path.as_deref().unwrap_or_default());
+        let extension = img_path.extension().and_then(|os_str| os_str.to_str()).unwrap_or_default().to_owned();
+        let save_image = |image: &image::DynamicImage, path: &std::path::Path| {
+            if raw_image {
+                let raw_distances = image.to_rgb32f().pixels().flat_map(|pixel| pixel.0[0].to_le_bytes()).collect::<Vec<_>>();
+                std::fs::write(path, raw_distances).expect("Failed to write output image");
+            } else {
+                image.save(path).expect("Failed to write output image");
+            }
+        };
+
+        let mut levels = vec![outbuf];
+        if args.write_mips {
+            for _ in 0..args.mip_levels {
+                levels.push(downsample(&levels[levels.len() - 1]));
+            }
+        }
+
+        if texture_container {
+            let texture = match Texture::new(&levels, args.block_compression) {
+                Ok(texture) => texture,
+                Err(error) => {
+                    eprintln!("Failed to encode output image: {error}");
+                    return;
+                },
+            };
+
+            let encoded = if extension.eq_ignore_ascii_case("ktx2") { texture.to_ktx2() } else { texture.to_dds() };
+            std::fs::write(&img_path, encoded).expect("Failed to write output image");
+        } else {
+            let stem = img_path.file_stem().and_then(|os_str| os_str.to_str()).unwrap_or_default().to_owned();
+            for (level, image) in levels.iter().enumerate() {
+                let file_name = match (level, extension.is_empty()) {
+                    (0, _) => img_path.file_name().unwrap_or_default().to_owned(),
+                    (_, true) => format!("{stem}_mip{level}").into(),
+                    (_, false) => format!("{stem}_mip{level}.{extension}").into(),
+                };
+                save_image(image, &img_path.with_file_name(file_name));
+            }
+        }
+    }
 
-    let meta_path = current_dir.join(&args.meta_path);
+    let meta_path = current_dir.join(meta_path);
     match meta_path.extension().map(|os_str| os_str.to_str()) {
         Some(Some("ron")) => {
             let serialized_meta = ron::ser::to_string_pretty(&out_metadata, ron::ser::PrettyConfig::default()).expect("Failed to serialize output metadata");
             std::fs::write(meta_path, serialized_meta).expect("Unable to write file");
         },
         Some(Some("json")) => {
-            if out_metadata.kerning_table.is_some() {
-                eprintln!("Cannot encode kerning table into JSON.");
-                eprintln!("This is because JSON requires dictionary keys to be strings,");
-                eprintln!("and we don't want to push this requirement into other formats.");
-                out_metadata.kerning_table = None;
+            // JSON requires dictionary keys to be strings, and we don't want to push
+            // this requirement into other formats, so use class-based kerning instead.
+            if let Some(kerning_table) = out_metadata.kerning_table.take() {
+                match kerning_classes(&kerning_table, MAX_JSON_KERNING_CLASSES_SIZE) {
+                    Some(kerning_classes) => out_metadata.kerning_classes = Some(kerning_classes),
+                    None => {
+                        eprintln!("Kerning classes would take up more than {} MiB, try another meta data format or --skip-kerning-table.", MAX_JSON_KERNING_CLASSES_SIZE >> 20);
+                        return;
+                    },
+                }
             }
 
             let serialized_meta = serde_json::to_string(&out_metadata).expect("Failed to serialize output metadata");
@@ -408,12 +1254,1240 @@ fn main() {
             std::fs::write(meta_path, serialized_meta).expect("Unable to write file");
         },
         _ => {
-            eprintln!("Failed to deduce meta data format from path: {}", args.meta_path);
+            eprintln!("Failed to deduce meta data format from path: {}", meta_path.display());
             eprintln!("Supported formats are: ron, json, rkyv");
-            eprintln!("Note that JSON serialization currently does not support kerning tables.");
             return;
         },
     }
 
     println!("Ok.");
 }
+
+/// Converts the output image into the pixel format that its meta data can carry.
+fn embed_bitmap(image: image::DynamicImage) -> Bitmap {
+    let (width, height) = (image.width(), image.height());
+    let (format, data) = match image {
+        image::DynamicImage::ImageLuma8(buffer) => (PixelFormat::Luma8, buffer.into_raw()),
+        image::DynamicImage::ImageLumaA8(buffer) => (PixelFormat::LumaAlpha8, buffer.into_raw()),
+        image::DynamicImage::ImageRgb8(buffer) => (PixelFormat::Rgb8, buffer.into_raw()),
+        image::DynamicImage::ImageRgba8(buffer) => (PixelFormat::Rgba8, buffer.into_raw()),
+        image::DynamicImage::ImageLuma16(buffer) => (PixelFormat::Luma16, buffer.into_raw().into_iter().flat_map(u16::to_le_bytes).collect()),
+        // Floating-point distance fields are stored in all channels of an RGB image
+        image => (PixelFormat::Luma32Float, image.to_rgb32f().pixels().flat_map(|pixel| pixel.0[0].to_le_bytes()).collect()),
+    };
+
+    Bitmap { width, height, format, data }
+}
+
+/// Pixel formats that GPUs can sample directly.
+#[derive(Clone, Copy, PartialEq)]
+enum TextureFormat {
+    R8,
+    Rg8,
+    Rgba8,
+    R16,
+    R32Float,
+    Bc4,
+    Bc5,
+}
+
+impl TextureFormat {
+    /// The side length of blocks of pixels that are stored together, and the number of bytes per block.
+    fn block_size(self) -> (u32, usize) {
+        match self {
+            TextureFormat::R8 => (1, 1),
+            TextureFormat::Rg8 | TextureFormat::R16 => (1, 2),
+            TextureFormat::Rgba8 | TextureFormat::R32Float => (1, 4),
+            TextureFormat::Bc4 => (4, 8),
+            TextureFormat::Bc5 => (4, 16),
+        }
+    }
+}
+
+/// An image along with its mipmaps, encoded for upload to the GPU as is.
+struct Texture {
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    levels: Vec<Vec<u8>>,
+}
+
+impl Texture {
+    fn new(levels: &[image::DynamicImage], block_compression: bool) -> Result<Self, String> {
+        let format = match (&levels[0], block_compression) {
+            (image::DynamicImage::ImageLuma8(_), false) => TextureFormat::R8,
+            (image::DynamicImage::ImageLuma8(_), true) => TextureFormat::Bc4,
+            (image::DynamicImage::ImageLumaA8(_), false) => TextureFormat::Rg8,
+            (image::DynamicImage::ImageLumaA8(_), true) => TextureFormat::Bc5,
+            (image::DynamicImage::ImageRgb8(_) | image::DynamicImage::ImageRgba8(_), false) => TextureFormat::Rgba8,
+            (image::DynamicImage::ImageLuma16(_), false) => TextureFormat::R16,
+            (image::DynamicImage::ImageRgb32F(_), false) => TextureFormat::R32Float,
+            (image, true) => return Err(format!("Block compression requires one or two 8-bit channels, but the image has {:?}", image.color())),
+            (image, false) => return Err(format!("Unsupported color type {:?}", image.color())),
+        };
+
+        let (width, height) = (levels[0].width(), levels[0].height());
+        let levels = levels.iter().map(|image| match format {
+            TextureFormat::R8 => image.to_luma8().into_raw(),
+            TextureFormat::Rg8 => image.to_luma_alpha8().into_raw(),
+            TextureFormat::Rgba8 => image.to_rgba8().into_raw(),
+            TextureFormat::R16 => image.to_luma16().into_raw().into_iter().flat_map(u16::to_le_bytes).collect(),
+            TextureFormat::R32Float => image.to_rgb32f().pixels().flat_map(|pixel| pixel.0[0].to_le_bytes()).collect(),
+            TextureFormat::Bc4 => compress_bc4(&image.to_luma8(), 1),
+            TextureFormat::Bc5 => compress_bc4(&image.to_luma_alpha8(), 2),
+        }).collect();
+
+        Ok(Texture { format, width, height, levels })
+    }
+
+    /// Encodes the texture as a KTX2 file, with a minimal data format descriptor and no key/value data.
+    fn to_ktx2(&self) -> Vec<u8> {
+        use ktx2::{ChannelTypeQualifiers, ColorModel, ColorPrimaries, DfdBlockHeaderBasic, DfdHeader, Format, Header, Index, LevelIndex, SampleInformation, TransferFunction};
+
+        let (block_side, block_bytes) = self.format.block_size();
+        let (format, color_model, channel_bits) = match self.format {
+            TextureFormat::R8 => (Format::R8_UNORM, ColorModel::RGBSDA, 8),
+            TextureFormat::Rg8 => (Format::R8G8_UNORM, ColorModel::RGBSDA, 8),
+            TextureFormat::Rgba8 => (Format::R8G8B8A8_UNORM, ColorModel::RGBSDA, 8),
+            TextureFormat::R16 => (Format::R16_UNORM, ColorModel::RGBSDA, 16),
+            TextureFormat::R32Float => (Format::R32_SFLOAT, ColorModel::RGBSDA, 32),
+            TextureFormat::Bc4 => (Format::BC4_UNORM_BLOCK, ColorModel::BC4, 64),
+            TextureFormat::Bc5 => (Format::BC5_UNORM_BLOCK, ColorModel::BC5, 64),
+        };
+
+        // One sample per channel, where the alpha channel has its own channel type
+        let channel_types: &[u8] = match self.format {
+            TextureFormat::Rg8 | TextureFormat::Bc5 => &[0, 1],
+            TextureFormat::Rgba8 => &[0, 1, 2, 15],
+            _ => &[0],
+        };
+        let samples = channel_types.iter().enumerate().map(|(index, &channel_type)| {
+            let (qualifiers, lower, upper) = match self.format {
+                TextureFormat::R32Float => (ChannelTypeQualifiers::FLOAT | ChannelTypeQualifiers::SIGNED, (-1.0f32).to_bits(), 1.0f32.to_bits()),
+                _ => (ChannelTypeQualifiers::empty(), 0, u32::MAX >> (32 - channel_bits.min(32))),
+            };
+
+            SampleInformation {
+                bit_offset: index as u16 * channel_bits as u16,
+                bit_length: NonZeroU8::new(channel_bits).unwrap(),
+                channel_type,
+                channel_type_qualifiers: qualifiers,
+                sample_positions: [0; 4],
+                lower,
+                upper,
+            }
+        });
+
+        let block_dimensions = NonZeroU8::new(block_side as u8).unwrap();
+        let one = NonZeroU8::new(1).unwrap();
+        let mut descriptor_block = DfdBlockHeaderBasic {
+            color_model: Some(color_model),
+            color_primaries: Some(ColorPrimaries::BT709),
+            transfer_function: Some(TransferFunction::Linear),
+            flags: ktx2::DataFormatFlags::STRAIGHT_ALPHA,
+            texel_block_dimensions: [block_dimensions, block_dimensions, one, one],
+            bytes_planes: [block_bytes as u8, 0, 0, 0, 0, 0, 0, 0],
+        }.as_bytes().to_vec();
+        descriptor_block.extend(samples.flat_map(|sample| sample.as_bytes()));
+
+        let mut dfd = vec![];
+        let dfd_length = (4 + DfdHeader::LENGTH + descriptor_block.len()) as u32;
+        dfd.extend(dfd_length.to_le_bytes());
+        dfd.extend(DfdHeader::BASIC.as_bytes(descriptor_block.len() as u16 + DfdHeader::LENGTH as u16));
+        dfd.extend(descriptor_block);
+
+        let dfd_offset = Header::LENGTH + LevelIndex::LENGTH * self.levels.len();
+        let header = Header {
+            format: Some(format),
+            type_size: if matches!(self.format, TextureFormat::Bc4 | TextureFormat::Bc5) { 1 } else { channel_bits as u32 / 8 },
+            pixel_width: self.width,
+            pixel_height: self.height,
+            pixel_depth: 0,
+            layer_count: 0,
+            face_count: 1,
+            level_count: self.levels.len() as u32,
+            supercompression_scheme: None,
+            index: Index {
+                dfd_byte_offset: dfd_offset as u32,
+                dfd_byte_length: dfd.len() as u32,
+                kvd_byte_offset: 0,
+                kvd_byte_length: 0,
+                sgd_byte_offset: 0,
+                sgd_byte_length: 0,
+            },
+        };
+
+        // Mip levels are stored from smallest to largest, each aligned to both its blocks and 4 bytes
+        let alignment = if block_bytes % 4 == 0 { block_bytes } else { 4 };
+        let mut data = vec![];
+        let mut level_indices = vec![LevelIndex { byte_offset: 0, byte_length: 0, uncompressed_byte_length: 0 }; self.levels.len()];
+        let data_offset = dfd_offset + dfd.len();
+        for (level, pixels) in self.levels.iter().enumerate().rev() {
+            data.resize((data_offset + data.len()).next_multiple_of(alignment) - data_offset, 0);
+            level_indices[level] = LevelIndex {
+                byte_offset: (data_offset + data.len()) as u64,
+                byte_length: pixels.len() as u64,
+                uncompressed_byte_length: pixels.len() as u64,
+            };
+            data.extend(pixels);
+        }
+
+        let mut encoded = header.as_bytes().to_vec();
+        encoded.extend(level_indices.iter().flat_map(LevelIndex::as_bytes));
+        encoded.extend(dfd);
+        encoded.extend(data);
+        encoded
+    }
+
+    /// Encodes the texture as a DDS file with a DX10 header.
+    fn to_dds(&self) -> Vec<u8> {
+        use ddsfile::{AlphaMode, D3D10ResourceDimension, Dds, DxgiFormat, NewDxgiParams};
+
+        let format = match self.format {
+            TextureFormat::R8 => DxgiFormat::R8_UNorm,
+            TextureFormat::Rg8 => DxgiFormat::R8G8_UNorm,
+            TextureFormat::Rgba8 => DxgiFormat::R8G8B8A8_UNorm,
+            TextureFormat::R16 => DxgiFormat::R16_UNorm,
+            TextureFormat::R32Float => DxgiFormat::R32_Float,
+            TextureFormat::Bc4 => DxgiFormat::BC4_UNorm,
+            TextureFormat::Bc5 => DxgiFormat::BC5_UNorm,
+        };
+
+        let mut dds = Dds::new_dxgi(NewDxgiParams {
+            height: self.height,
+            width: self.width,
+            depth: None,
+            format,
+            mipmap_levels: Some(self.levels.len() as u32),
+            array_layers: None,
+            caps2: None,
+            is_cubemap: false,
+            resource_dimension: D3D10ResourceDimension::Texture2D,
+            alpha_mode: AlphaMode::Straight,
+        }).expect("Failed to create DDS header");
+        dds.data = self.levels.concat();
+
+        let mut encoded = vec![];
+        dds.write(&mut encoded).expect("Failed to encode DDS image");
+        encoded
+    }
+}
+
+/// Compresses each channel of an 8-bit image into BC4 blocks, which BC5 interleaves for two channels.
+fn compress_bc4<P: image::Pixel<Subpixel = u8>>(image: &image::ImageBuffer<P, Vec<u8>>, channels: usize) -> Vec<u8> {
+    let mut compressed = vec![];
+    for block_y in (0..image.height()).step_by(4) {
+        for block_x in (0..image.width()).step_by(4) {
+            for channel in 0..channels {
+                // Blocks extend beyond the edges of the image, so repeat the last row and column
+                let values = std::array::from_fn::<u8, 16, _>(|index| {
+                    let x = (block_x + index as u32 % 4).min(image.width() - 1);
+                    let y = (block_y + index as u32 / 4).min(image.height() - 1);
+                    image.get_pixel(x, y).channels()[channel]
+                });
+
+                // Interpolate eight values between the extremes, with the maximum first
+                let (max, min) = (*values.iter().max().unwrap(), *values.iter().min().unwrap());
+                let palette = std::array::from_fn::<f32, 8, _>(|index| match index {
+                    0 => f32::from(max),
+                    1 => f32::from(min),
+                    _ => ((8 - index) as f32 * f32::from(max) + (index - 1) as f32 * f32::from(min)) / 7.0,
+                });
+
+                let indices = values.iter().enumerate().fold(0u64, |indices, (pixel, &value)| {
+                    let nearest = (0..8).min_by(|&a, &b| {
+                        (palette[a] - f32::from(value)).abs().total_cmp(&(palette[b] - f32::from(value)).abs())
+                    }).unwrap();
+                    indices | (nearest as u64) << (pixel * 3)
+                });
+
+                compressed.extend([max, min]);
+                compressed.extend(&indices.to_le_bytes()[..6]);
+            }
+        }
+    }
+
+    compressed
+}
+
+/// Halves the size of an image by averaging each block of 2x2 pixels, like mipmaps are typically generated.
+fn downsample(image: &image::DynamicImage) -> image::DynamicImage {
+    let source = image.to_rgba32f();
+    let (width, height) = ((source.width() / 2).max(1), (source.height() / 2).max(1));
+    let half = image::DynamicImage::ImageRgba32F(image::ImageBuffer::from_fn(width, height, |x, y| {
+        let mut sum = [0.0; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = source.get_pixel((x * 2 + dx).min(source.width() - 1), (y * 2 + dy).min(source.height() - 1));
+            for (sum, value) in sum.iter_mut().zip(pixel.0) {
+                *sum += value / 4.0;
+            }
+        }
+
+        image::Rgba(sum)
+    }));
+
+    match image {
+        image::DynamicImage::ImageLuma8(_) => image::DynamicImage::ImageLuma8(half.to_luma8()),
+        image::DynamicImage::ImageLumaA8(_) => image::DynamicImage::ImageLumaA8(half.to_luma_alpha8()),
+        image::DynamicImage::ImageRgb8(_) => image::DynamicImage::ImageRgb8(half.to_rgb8()),
+        image::DynamicImage::ImageRgba8(_) => image::DynamicImage::ImageRgba8(half.to_rgba8()),
+        image::DynamicImage::ImageLuma16(_) => image::DynamicImage::ImageLuma16(half.to_luma16()),
+        image::DynamicImage::ImageRgb32F(_) => image::DynamicImage::ImageRgb32F(half.to_rgb32f()),
+        _ => half,
+    }
+}
+
+/// Grows a row-major coverage bitmap of the given width by a radius in pixels, with anti-aliased edges.
+fn dilate(coverage: &[f32], width: usize, radius: f32) -> Vec<f32> {
+    let height = coverage.len() / width;
+    let reach = radius.ceil() as isize + 1;
+
+    let mut dilated = vec![0.0f32; coverage.len()];
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let mut value = 0.0f32;
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    let (sx, sy) = (x + dx, y + dy);
+                    if sx < 0 || sy < 0 || sx >= width as isize || sy >= height as isize {
+                        continue;
+                    }
+
+                    let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                    let weight = (radius + 0.5 - distance).clamp(0.0, 1.0);
+                    value = value.max(coverage[sy as usize * width + sx as usize] * weight);
+                }
+            }
+
+            dilated[y as usize * width + x as usize] = value;
+        }
+    }
+
+    dilated
+}
+
+/// Blurs a row-major coverage bitmap of the given width with a gaussian kernel of the given radius in pixels.
+fn blur(coverage: &[f32], width: usize, radius: f32) -> Vec<f32> {
+    if radius <= 0.0 {
+        return coverage.to_vec();
+    }
+
+    let height = coverage.len() / width;
+    let reach = radius.ceil() as isize;
+    let sigma = radius / 2.0;
+    let kernel = (-reach..=reach).map(|d| (-(d * d) as f32 / (2.0 * sigma * sigma)).exp()).collect::<Vec<_>>();
+    let total = kernel.iter().sum::<f32>();
+
+    let convolve = |source: &[f32], step: (isize, isize)| {
+        let mut blurred = vec![0.0f32; source.len()];
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let mut value = 0.0;
+                for (d, weight) in (-reach..=reach).zip(kernel.iter()) {
+                    let (sx, sy) = (x + d * step.0, y + d * step.1);
+                    if sx >= 0 && sy >= 0 && sx < width as isize && sy < height as isize {
+                        value += source[sy as usize * width + sx as usize] * weight;
+                    }
+                }
+
+                blurred[y as usize * width + x as usize] = value / total;
+            }
+        }
+
+        blurred
+    };
+
+    convolve(&convolve(coverage, (1, 0)), (0, 1))
+}
+
+/// Parses a distance range in pixels written as `outside,inside`, or as a single distance for both.
+fn parse_range(range: &str) -> Result<(f32, f32), String> {
+    let (outside, inside) = range.split_once(',').unwrap_or((range, range));
+    let outside = outside.trim().parse::<f32>().map_err(|e| format!("Invalid outside range {outside}: {e}"))?;
+    let inside = inside.trim().parse::<f32>().map_err(|e| format!("Invalid inside range {inside}: {e}"))?;
+    if outside <= 0.0 || inside <= 0.0 {
+        return Err(format!("Expected positive distances but got {range}"));
+    }
+
+    Ok((outside, inside))
+}
+
+/// Maps a signed distance in pixels to a value between 0 and 255, the inverse of [`DistanceField::distance`].
+fn encode_distance(distance_field: &DistanceField, signed_distance: f32) -> f32 {
+    let normalize = |distance: f32, range: f32| match distance_field.mapping {
+        DistanceMapping::Linear => (distance / range).clamp(0.0, 1.0),
+        DistanceMapping::Squared => (distance / range).powi(2).clamp(0.0, 1.0),
+    };
+
+    let edge_value = distance_field.edge_value;
+    if signed_distance < 0.0 {
+        edge_value * (1.0 - normalize(-signed_distance, distance_field.outside_range))
+    } else {
+        edge_value + (255.0 - edge_value) * normalize(signed_distance, distance_field.inside_range)
+    }
+}
+
+/// Parses an offset in pixels written as `x,y`.
+fn parse_offset(offset: &str) -> Result<(i32, i32), String> {
+    let (x, y) = offset.split_once(',').ok_or_else(|| format!("Expected an offset like 2,2 but got {offset}"))?;
+    let x = x.trim().parse().map_err(|e| format!("Invalid horizontal offset {x}: {e}"))?;
+    let y = y.trim().parse().map_err(|e| format!("Invalid vertical offset {y}: {e}"))?;
+    Ok((x, y))
+}
+
+/// The most memory that the kerning classes of fonts written to JSON may take up, which only fonts with
+/// huge character sets and little regularity to their kerning come anywhere near.
+const MAX_JSON_KERNING_CLASSES_SIZE: usize = 64 << 20;
+
+/// Converts a flat kerning table into the equivalent class-based representation.
+/// 
+/// Characters whose adjustments against all other characters are identical are grouped into the same class,
+/// which recovers the kerning classes of the source font, if any.
+/// 
+/// Returns None if there are too many classes to number with a `u16`, or if the result would take up
+/// at least `max_size` bytes when serialized, roughly.
+fn kerning_classes(kerning_table: &HashMap<(char, char), f32>, max_size: usize) -> Option<KerningClasses> {
+    let mut rows = HashMap::<char, Vec<(char, u32)>>::new();
+    let mut columns = HashMap::<char, Vec<(char, u32)>>::new();
+    for (&(left, right), &adjustment) in kerning_table.iter() {
+        rows.entry(left).or_default().push((right, adjustment.to_bits()));
+        columns.entry(right).or_default().push((left, adjustment.to_bits()));
+    }
+
+    // Class 0 is reserved for characters without any kerning
+    let assign_classes = |vectors: HashMap<char, Vec<(char, u32)>>| {
+        let mut class_ids = HashMap::<Vec<(char, u32)>, u16>::new();
+        let mut classes = HashMap::new();
+        for (character, mut vector) in vectors {
+            vector.sort_unstable();
+            let next_id = u16::try_from(class_ids.len() + 1).ok()?;
+            classes.insert(character, *class_ids.entry(vector).or_insert(next_id));
+        }
+
+        Some((classes, u16::try_from(class_ids.len() + 1).ok()?))
+    };
+
+    let (left_classes, left_class_count) = assign_classes(rows)?;
+    let (right_classes, right_class_count) = assign_classes(columns)?;
+
+    let classes_size = (left_classes.len() + right_classes.len()) * 8 + left_class_count as usize * right_class_count as usize * 4;
+    if classes_size >= max_size {
+        return None;
+    }
+
+    let mut adjustments = vec![0.0; left_class_count as usize * right_class_count as usize];
+    for (&(left, right), &adjustment) in kerning_table.iter() {
+        let index = left_classes[&left] as usize * right_class_count as usize + right_classes[&right] as usize;
+        adjustments[index] = adjustment;
+    }
+
+    Some(KerningClasses { left_classes, right_classes, right_class_count, adjustments })
+}
+
+/// Collects the kerning adjustments between all pairs of the given glyphs from the legacy `kern` table, in font units.
+/// 
+/// Like most text renderers, this only considers the first horizontal subtable that has an entry for a given pair.
+fn legacy_kerning_pairs<T>(kern: &Kern, glyphs: &HashMap<GlyphId, T>) -> HashMap<(GlyphId, GlyphId), i32> {
+    let mut pairs = HashMap::new();
+    let subtables = kern.subtables()
+        .filter_map(Result::ok)
+        .filter(|subtable| subtable.is_horizontal() && !subtable.is_cross_stream() && !subtable.is_variable());
+
+    for subtable in subtables {
+        match subtable.kind() {
+            Ok(SubtableKind::Format0(format0)) => {
+                for pair in format0.pairs() {
+                    let (left, right) = (GlyphId::from(pair.left()), GlyphId::from(pair.right()));
+                    if glyphs.contains_key(&left) && glyphs.contains_key(&right) {
+                        pairs.entry((left, right)).or_insert(pair.value() as i32);
+                    }
+                }
+            },
+            // These formats are rare enough in practice that testing every pair is fine
+            Ok(SubtableKind::Format2(format2)) => {
+                for &left in glyphs.keys() {
+                    for &right in glyphs.keys() {
+                        if let Some(value) = format2.kerning(left, right) {
+                            pairs.entry((left, right)).or_insert(value);
+                        }
+                    }
+                }
+            },
+            Ok(SubtableKind::Format3(format3)) => {
+                for &left in glyphs.keys() {
+                    for &right in glyphs.keys() {
+                        if let Some(value) = format3.kerning(left, right) {
+                            pairs.entry((left, right)).or_insert(value);
+                        }
+                    }
+                }
+            },
+            Ok(SubtableKind::Format1(_)) | Err(_) => {},
+        }
+    }
+
+    pairs
+}
+
+/// Collects the indices of all lookups referenced by any of the given features, in lookup list order.
+fn feature_lookup_indices(feature_list: &FeatureList, feature_tags: &[&str]) -> Vec<u16> {
+    let mut lookup_indices = feature_list.feature_records().iter()
+        .filter(|record| feature_tags.iter().any(|&tag| record.feature_tag() == tag))
+        .filter_map(|record| record.feature(feature_list.offset_data()).ok())
+        .flat_map(|feature| feature.lookup_list_indices().iter().map(|index| index.get()))
+        .collect::<Vec<_>>();
+    lookup_indices.sort_unstable();
+    lookup_indices.dedup();
+    lookup_indices
+}
+
+/// Finds all ligatures that the lookups of the given GSUB features can form from the given glyphs,
+/// along with the character sequences they replace.
+/// 
+/// Ligatures formed by earlier lookups may serve as components of ligatures in later lookups.
+fn gsub_ligatures(gsub: &Gsub, feature_tags: &[&str], glyph_chars: &HashMap<GlyphId, Vec<char>>) -> Vec<(String, GlyphId)> {
+    let (Ok(feature_list), Ok(lookup_list)) = (gsub.feature_list(), gsub.lookup_list()) else {
+        return vec![];
+    };
+
+    let mut glyph_sequences = glyph_chars.iter()
+        .map(|(&glyph_id, chars)| (glyph_id, chars.iter().map(char::to_string).collect::<Vec<_>>()))
+        .collect::<HashMap<_, _>>();
+
+    let mut ligatures = vec![];
+    for lookup_index in feature_lookup_indices(&feature_list, feature_tags) {
+        let Ok(lookup) = lookup_list.lookups().get(lookup_index as usize) else {
+            continue;
+        };
+
+        let Ok(SubstitutionSubtables::Ligature(subtables)) = lookup.subtables() else {
+            continue;
+        };
+
+        let mut lookup_ligatures = vec![];
+        for subtable in subtables.iter().filter_map(Result::ok) {
+            let Ok(coverage) = subtable.coverage() else {
+                continue;
+            };
+
+            for (coverage_index, first) in coverage.iter().enumerate() {
+                let Some(first_sequences) = glyph_sequences.get(&GlyphId::from(first)) else {
+                    continue;
+                };
+
+                let Ok(ligature_set) = subtable.ligature_sets().get(coverage_index) else {
+                    continue;
+                };
+
+                for ligature in ligature_set.ligatures().iter().filter_map(Result::ok) {
+                    // Expand to every combination of character sequences that map to the components
+                    let mut sequences = first_sequences.clone();
+                    for component in ligature.component_glyph_ids() {
+                        let Some(component_sequences) = glyph_sequences.get(&GlyphId::from(component.get())) else {
+                            sequences.clear();
+                            break;
+                        };
+
+                        sequences = sequences.iter()
+                            .flat_map(|prefix| component_sequences.iter().map(move |suffix| format!("{prefix}{suffix}")))
+                            .collect();
+                    }
+
+                    let ligature_glyph = GlyphId::from(ligature.ligature_glyph());
+                    lookup_ligatures.extend(sequences.into_iter().map(|sequence| (sequence, ligature_glyph)));
+                }
+            }
+        }
+
+        for (sequence, ligature_glyph) in lookup_ligatures {
+            glyph_sequences.entry(ligature_glyph).or_default().push(sequence.clone());
+            ligatures.push((sequence, ligature_glyph));
+        }
+    }
+
+    ligatures.sort_unstable();
+    ligatures.dedup();
+    ligatures
+}
+
+/// Collects the pair adjustment subtables of all lookups referenced by the `kern` feature, grouped by lookup.
+/// 
+/// Lookups are returned in lookup list order, which is the order in which they are applied during shaping.
+fn gpos_kerning_lookups<'a>(gpos: &Gpos<'a>) -> Vec<Vec<PairPos<'a>>> {
+    let (Ok(feature_list), Ok(lookup_list)) = (gpos.feature_list(), gpos.lookup_list()) else {
+        return vec![];
+    };
+
+    feature_lookup_indices(&feature_list, &["kern"]).into_iter()
+        .filter_map(|index| lookup_list.lookups().get(index as usize).ok())
+        .filter_map(|lookup| match lookup.subtables().ok()? {
+            PositionSubtables::Pair(subtables) => Some(subtables.iter().filter_map(Result::ok).collect()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Collects the kerning adjustments between all pairs of the given glyphs from the GPOS `kern` lookups, in font units.
+/// 
+/// Only the horizontal advance adjustment of the first glyph is considered, which is how virtually all fonts
+/// encode kerning for horizontal text. Within each lookup, the first subtable that matches a pair applies,
+/// and the adjustments of all lookups are accumulated.
+fn gpos_kerning_pairs<T>(lookups: &[Vec<PairPos>], glyphs: &HashMap<GlyphId, T>, context: &ValueContext) -> HashMap<(GlyphId, GlyphId), i32> {
+    let mut pairs = HashMap::new();
+    for subtables in lookups.iter() {
+        let mut lookup_pairs = HashMap::new();
+        // Left glyphs matched by a class-based subtable, which applies to every right glyph
+        let mut claimed = HashSet::new();
+
+        for subtable in subtables.iter() {
+            let coverage = match subtable {
+                PairPos::Format1(format1) => format1.coverage(),
+                PairPos::Format2(format2) => format2.coverage(),
+            };
+
+            let Ok(coverage) = coverage else {
+                continue;
+            };
+
+            let covered_glyphs = coverage.iter()
+                .enumerate()
+                .map(|(coverage_index, left)| (coverage_index, GlyphId::from(left)))
+                .filter(|(_, left)| glyphs.contains_key(left) && !claimed.contains(left));
+
+            match subtable {
+                PairPos::Format1(format1) => {
+                    for (coverage_index, left) in covered_glyphs {
+                        let Ok(pair_set) = format1.pair_sets().get(coverage_index) else {
+                            continue;
+                        };
+
+                        for record in pair_set.pair_value_records().iter().filter_map(Result::ok) {
+                            let right = GlyphId::from(record.second_glyph());
+                            if !glyphs.contains_key(&right) {
+                                continue;
+                            }
+
+                            if let Ok(value) = record.value_record1().value(pair_set.offset_data(), context) {
+                                lookup_pairs.entry((left, right)).or_insert(value.x_advance as i32 + value.x_advance_delta);
+                            }
+                        }
+                    }
+                },
+                PairPos::Format2(format2) => {
+                    let (Ok(class_def1), Ok(class_def2)) = (format2.class_def1(), format2.class_def2()) else {
+                        continue;
+                    };
+
+                    let mut right_classes = HashMap::<u16, Vec<GlyphId>>::new();
+                    for &right in glyphs.keys() {
+                        right_classes.entry(class_def2.get(right)).or_default().push(right);
+                    }
+
+                    // Many left glyphs typically share a class, so only resolve each row of the class matrix once
+                    let mut class_rows = HashMap::<u16, Vec<(u16, i32)>>::new();
+                    for (_, left) in covered_glyphs.collect::<Vec<_>>() {
+                        let class1 = class_def1.get(left);
+                        let row = class_rows.entry(class1).or_insert_with(|| {
+                            right_classes.keys()
+                                .filter_map(|&class2| {
+                                    let [value, _] = format2.values(class1, class2, context).ok()?;
+                                    Some((class2, value.x_advance as i32 + value.x_advance_delta))
+                                })
+                                .filter(|&(_, adjustment)| adjustment != 0)
+                                .collect()
+                        });
+
+                        for &(class2, adjustment) in row.iter() {
+                            for &right in right_classes[&class2].iter() {
+                                lookup_pairs.entry((left, right)).or_insert(adjustment);
+                            }
+                        }
+
+                        claimed.insert(left);
+                    }
+                },
+            }
+        }
+
+        for (pair, adjustment) in lookup_pairs {
+            *pairs.entry(pair).or_insert(0) += adjustment;
+        }
+    }
+
+    pairs
+}
+
+/// Identifies the entry of the output [`BitmapFont`] that a rasterized glyph belongs to.
+enum GlyphKey {
+    Char(char),
+    Ligature(String),
+    Id(u32),
+}
+
+impl fmt::Display for GlyphKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GlyphKey::Char(character) => write!(f, "{:x}", *character as u32),
+            GlyphKey::Ligature(sequence) => write!(f, "for ligature \"{sequence}\""),
+            GlyphKey::Id(glyph_id) => write!(f, "with ID {glyph_id}"),
+        }
+    }
+}
+
+/// Integer pixel bounds of a glyph, with inclusive minimum and exclusive maximum.
+struct PixelRect {
+    min_x: i32,
+    min_y: i32,
+    max_x: i32,
+    max_y: i32,
+}
+
+impl PixelRect {
+    fn width(&self) -> i32 {
+        self.max_x - self.min_x
+    }
+
+    fn height(&self) -> i32 {
+        self.max_y - self.min_y
+    }
+}
+
+enum Curve {
+    Line(Point, Point),
+    Quad(Point, Point, Point),
+    Cubic(Point, Point, Point, Point),
+}
+
+/// A glyph outline scaled to pixel space, with the y-axis pointing downwards.
+#[derive(Default)]
+struct GlyphOutline {
+    curves: Vec<Curve>,
+    /// Indices of the first curve of each contour.
+    contours: Vec<usize>,
+    start: Point,
+    current: Point,
+    subpixel_offset: f32,
+}
+
+impl GlyphOutline {
+    fn load(outlines: &OutlineGlyphCollection, glyph_id: GlyphId, draw_settings: DrawSettings) -> Self {
+        let mut outline = GlyphOutline::default();
+        if let Some(glyph) = outlines.get(glyph_id) {
+            if let Err(error) = glyph.draw(draw_settings, &mut outline) {
+                eprintln!("Failed to load outline of glyph {}: {error}", glyph_id.to_u32());
+                outline.curves.clear();
+            }
+        }
+
+        outline
+    }
+
+    /// Shifts the outline to the right by a fraction of a pixel when rasterizing it,
+    /// without affecting the left edge of its [pixel bounding box](GlyphOutline::pixel_bounding_box).
+    fn with_subpixel_offset(self, subpixel_offset: f32) -> Self {
+        GlyphOutline { subpixel_offset, ..self }
+    }
+
+    /// Emboldens the outline by moving each point outwards by half the given strength in pixels, like FreeType does.
+    /// The outline is also shifted up and to the right by as much, so that it grows by the full strength in either
+    /// direction while staying on the baseline.
+    fn embolden(mut self, strength: f32) -> Self {
+        let points = |curve: &Curve| match *curve {
+            Curve::Line(_, p1) => vec![p1],
+            Curve::Quad(_, p1, p2) => vec![p1, p2],
+            Curve::Cubic(_, p1, p2, p3) => vec![p1, p2, p3],
+        };
+
+        // Contours are closed, so the end points of their curves form closed polygons along with the control points
+        let contours = self.contours.iter().zip(self.contours.iter().skip(1).chain([&self.curves.len()]))
+            .map(|(&start, &end)| start..end)
+            .filter(|contour| !contour.is_empty())
+            .collect::<Vec<_>>();
+        let polygons = contours.iter()
+            .map(|contour| self.curves[contour.clone()].iter().flat_map(points).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        // Holes run the opposite way to outer contours, so moving points to the same side of every edge
+        // shrinks holes while growing everything else
+        let area = polygons.iter()
+            .flat_map(|polygon| polygon.iter().zip(polygon.iter().cycle().skip(1)))
+            .map(|(p, q)| p.x * q.y - q.x * p.y)
+            .sum::<f32>();
+        let orientation = if area < 0.0 { 1.0 } else { -1.0 };
+        let normal = |from: Point, to: Point| {
+            let (dx, dy) = (to.x - from.x, to.y - from.y);
+            let length = (dx * dx + dy * dy).sqrt();
+            (length > f32::EPSILON).then(|| point(-dy * orientation / length, dx * orientation / length))
+        };
+
+        let half = strength / 2.0;
+        for (contour, polygon) in contours.into_iter().zip(polygons) {
+            let n = polygon.len();
+            let moved = (0..n).map(|i| {
+                let (previous, current, next) = (polygon[(i + n - 1) % n], polygon[i], polygon[(i + 1) % n]);
+                let shift = match (normal(previous, current), normal(current, next)) {
+                    (Some(a), Some(b)) => {
+                        // Scale the bisector so that both adjacent edges move by the same distance,
+                        // but leave spikes alone rather than extending them indefinitely
+                        let d = 1.0 + a.x * b.x + a.y * b.y;
+                        if d > 0.06 { point((a.x + b.x) / d, (a.y + b.y) / d) } else { point(0.0, 0.0) }
+                    },
+                    (Some(a), None) | (None, Some(a)) => a,
+                    (None, None) => point(0.0, 0.0),
+                };
+
+                point(current.x + (shift.x + 1.0) * half, current.y + (shift.y - 1.0) * half)
+            }).collect::<Vec<_>>();
+
+            let mut current = moved[n - 1];
+            let mut points = moved.into_iter();
+            for curve in &mut self.curves[contour] {
+                let mut next = || points.next().expect("Contour has fewer points than its curves");
+                *curve = match curve {
+                    Curve::Line(..) => Curve::Line(current, next()),
+                    Curve::Quad(..) => Curve::Quad(current, next(), next()),
+                    Curve::Cubic(..) => Curve::Cubic(current, next(), next(), next()),
+                };
+                current = match *curve {
+                    Curve::Line(_, to) | Curve::Quad(_, _, to) | Curve::Cubic(_, _, _, to) => to,
+                };
+            }
+        }
+
+        self
+    }
+
+    /// Slants the outline to the right by the given angle in degrees, shearing it about the baseline.
+    fn oblique(mut self, angle: f32) -> Self {
+        let slant = angle.to_radians().tan();
+        let shear = |p: Point| point(p.x - p.y * slant, p.y);
+        for curve in &mut self.curves {
+            *curve = match *curve {
+                Curve::Line(p0, p1) => Curve::Line(shear(p0), shear(p1)),
+                Curve::Quad(p0, p1, p2) => Curve::Quad(shear(p0), shear(p1), shear(p2)),
+                Curve::Cubic(p0, p1, p2, p3) => Curve::Cubic(shear(p0), shear(p1), shear(p2), shear(p3)),
+            };
+        }
+
+        self
+    }
+
+    fn pixel_bounding_box(&self) -> Option<PixelRect> {
+        let mut points = self.curves.iter().flat_map(|curve| match *curve {
+            Curve::Line(p0, p1) => vec![p0, p1],
+            Curve::Quad(p0, p1, p2) => vec![p0, p1, p2],
+            Curve::Cubic(p0, p1, p2, p3) => vec![p0, p1, p2, p3],
+        });
+
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), p| {
+            (point(min.x.min(p.x), min.y.min(p.y)), point(max.x.max(p.x), max.y.max(p.y)))
+        });
+
+        let rect = PixelRect {
+            min_x: min.x.floor() as i32,
+            min_y: min.y.floor() as i32,
+            max_x: (max.x + self.subpixel_offset).ceil() as i32,
+            max_y: max.y.ceil() as i32,
+        };
+
+        (rect.width() > 0 && rect.height() > 0).then_some(rect)
+    }
+
+    /// Rasterizes the outline, calling `o` with the coordinates of each pixel relative to the
+    /// [pixel bounding box](GlyphOutline::pixel_bounding_box) and its coverage.
+    fn draw(&self, o: impl FnMut(u32, u32, f32)) {
+        self.draw_supersampled(1, o);
+    }
+
+    /// Like [`GlyphOutline::draw`], but rasterizes the outline at `factor` times the resolution,
+    /// calling `o` for each of the `factor * factor` cells of every pixel.
+    fn draw_supersampled(&self, factor: u32, o: impl FnMut(u32, u32, f32)) {
+        let Some(bounding_box) = self.pixel_bounding_box() else {
+            return;
+        };
+
+        let offset = point(self.subpixel_offset - bounding_box.min_x as f32, -bounding_box.min_y as f32);
+        let factor = factor as f32;
+        let mut rasterizer = Rasterizer::new((bounding_box.width() as f32 * factor) as usize, (bounding_box.height() as f32 * factor) as usize);
+        self.rasterize(&mut rasterizer, |p| point((p.x + offset.x) * factor, (p.y + offset.y) * factor));
+        rasterizer.for_each_pixel_2d(o);
+    }
+
+    /// Rasterizes the outline at three times the horizontal resolution, calling `o` with the coordinates of each pixel
+    /// relative to the [pixel bounding box](GlyphOutline::pixel_bounding_box) widened by one pixel on either side,
+    /// and the coverage of its three subpixels from left to right.
+    fn draw_subpixel(&self, mut o: impl FnMut(u32, u32, [f32; 3])) {
+        // FreeType's default FIR filter, which reduces color fringes while preserving overall brightness
+        const FILTER: [f32; 5] = [8.0 / 256.0, 77.0 / 256.0, 86.0 / 256.0, 77.0 / 256.0, 8.0 / 256.0];
+
+        let Some(bounding_box) = self.pixel_bounding_box() else {
+            return;
+        };
+
+        let width = (bounding_box.width() + 2) as usize;
+        let height = bounding_box.height() as usize;
+        let (min_x, min_y) = ((bounding_box.min_x - 1) as f32 - self.subpixel_offset, bounding_box.min_y as f32);
+        let mut rasterizer = Rasterizer::new(width * 3, height);
+        self.rasterize(&mut rasterizer, |p| point((p.x - min_x) * 3.0, p.y - min_y));
+
+        let mut coverage = vec![0.0; width * 3 * height];
+        rasterizer.for_each_pixel_2d(|x, y, v| coverage[y as usize * width * 3 + x as usize] = v);
+
+        for (y, row) in coverage.chunks_exact(width * 3).enumerate() {
+            for x in 0..width {
+                let subpixels = [0, 1, 2].map(|channel| {
+                    let center = x * 3 + channel;
+                    FILTER.iter().enumerate()
+                        .filter_map(|(tap, weight)| Some(weight * row.get((center + tap).checked_sub(2)?)?))
+                        .sum::<f32>()
+                        .min(1.0)
+                });
+
+                o(x as u32, y as u32, subpixels);
+            }
+        }
+    }
+
+    fn rasterize(&self, rasterizer: &mut Rasterizer, transform: impl Fn(Point) -> Point) {
+        for curve in self.curves.iter() {
+            match *curve {
+                Curve::Line(p0, p1) => rasterizer.draw_line(transform(p0), transform(p1)),
+                Curve::Quad(p0, p1, p2) => rasterizer.draw_quad(transform(p0), transform(p1), transform(p2)),
+                Curve::Cubic(p0, p1, p2, p3) => rasterizer.draw_cubic(transform(p0), transform(p1), transform(p2), transform(p3)),
+            }
+        }
+    }
+}
+
+impl OutlinePen for GlyphOutline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.close();
+        self.contours.push(self.curves.len());
+        self.start = point(x, -y);
+        self.current = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = point(x, -y);
+        self.curves.push(Curve::Line(self.current, to));
+        self.current = to;
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        let to = point(x, -y);
+        self.curves.push(Curve::Quad(self.current, point(cx0, -cy0), to));
+        self.current = to;
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        let to = point(x, -y);
+        self.curves.push(Curve::Cubic(self.current, point(cx0, -cy0), point(cx1, -cy1), to));
+        self.current = to;
+    }
+
+    fn close(&mut self) {
+        if self.current != self.start {
+            self.curves.push(Curve::Line(self.current, self.start));
+            self.current = self.start;
+        }
+    }
+}
+
+/// A monochrome glyph in pixel space, with the y-axis pointing downwards.
+enum Glyph {
+    Outline(GlyphOutline),
+    /// An embedded bitmap of coverage values, positioned by its top left corner relative to the glyph origin.
+    Bitmap(image::GrayImage, i32, i32),
+}
+
+impl Glyph {
+    /// Decodes an embedded bitmap, which is used as is, without scaling.
+    fn from_bitmap(bitmap: &skrifa::bitmap::BitmapGlyph) -> Option<Self> {
+        let BitmapData::Mask(mask) = &bitmap.data else {
+            return None;
+        };
+
+        let coverage = mask.decode(bitmap.width, bitmap.height).ok()?;
+        let image = image::GrayImage::from_raw(bitmap.width, bitmap.height, coverage)?;
+        Some(Glyph::Bitmap(image, bitmap.inner_bearing_x as i32, -bitmap.inner_bearing_y as i32))
+    }
+
+    fn pixel_bounding_box(&self) -> Option<PixelRect> {
+        match self {
+            Glyph::Outline(outline) => outline.pixel_bounding_box(),
+            Glyph::Bitmap(image, left, top) => (image.width() > 0 && image.height() > 0).then(|| PixelRect {
+                min_x: *left,
+                min_y: *top,
+                max_x: left + image.width() as i32,
+                max_y: top + image.height() as i32,
+            }),
+        }
+    }
+
+    /// Rasterizes the glyph, calling `o` with the coordinates of each pixel relative to the
+    /// [pixel bounding box](Glyph::pixel_bounding_box) and its coverage.
+    fn draw(&self, mut o: impl FnMut(u32, u32, f32)) {
+        match self {
+            Glyph::Outline(outline) => outline.draw(o),
+            Glyph::Bitmap(image, _, _) => {
+                for (x, y, pixel) in image.enumerate_pixels() {
+                    o(x, y, pixel.0[0] as f32 / 255.0);
+                }
+            },
+        }
+    }
+
+    /// Like [`Glyph::draw`], but at `factor` times the resolution, see [`GlyphOutline::draw_supersampled`].
+    fn draw_supersampled(&self, factor: u32, mut o: impl FnMut(u32, u32, f32)) {
+        match self {
+            Glyph::Outline(outline) => outline.draw_supersampled(factor, o),
+            // Embedded bitmaps have no finer detail to offer
+            Glyph::Bitmap(image, _, _) => {
+                for (x, y, pixel) in image.enumerate_pixels() {
+                    for (cx, cy) in (0..factor).flat_map(|cy| (0..factor).map(move |cx| (cx, cy))) {
+                        o(x * factor + cx, y * factor + cy, pixel.0[0] as f32 / 255.0);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Like [`Glyph::draw`], but with subpixel anti-aliasing, see [`GlyphOutline::draw_subpixel`].
+    fn draw_subpixel(&self, mut o: impl FnMut(u32, u32, [f32; 3])) {
+        match self {
+            Glyph::Outline(outline) => outline.draw_subpixel(o),
+            // Embedded bitmaps are pixel-exact already
+            Glyph::Bitmap(image, _, _) => {
+                for (x, y, pixel) in image.enumerate_pixels() {
+                    o(x + 1, y, [pixel.0[0] as f32 / 255.0; 3]);
+                }
+            },
+        }
+    }
+}
+
+/// The tables of a font that define color glyphs.
+struct ColorTables<'a> {
+    colr: Option<Colr<'a>>,
+    palette: Vec<[u8; 4]>,
+    strikes: Option<BitmapStrikes<'a>>,
+}
+
+impl<'a> ColorTables<'a> {
+    fn new(font: &FontRef<'a>) -> Self {
+        // Only the first palette is used, which is the default one
+        let palette = font.cpal().ok().and_then(|cpal| {
+            let first_index = cpal.color_record_indices().first()?.get() as usize;
+            let records = cpal.color_records_array()?.ok()?;
+            let records = records.get(first_index..first_index + cpal.num_palette_entries() as usize)?;
+            Some(records.iter().map(|record| [record.red(), record.green(), record.blue(), record.alpha()]).collect())
+        });
+
+        // Monochrome EBDT strikes are not color glyphs
+        let strikes = BitmapStrikes::with_format(font, BitmapFormat::Sbix)
+            .or_else(|| BitmapStrikes::with_format(font, BitmapFormat::Cbdt));
+
+        ColorTables { colr: font.colr().ok(), palette: palette.unwrap_or_default(), strikes }
+    }
+}
+
+/// A color glyph scaled to pixel space, with the y-axis pointing downwards.
+enum ColorGlyph {
+    /// Outlines of other glyphs, filled with a solid color each and drawn on top of each other in order.
+    Layers(Vec<(GlyphOutline, [u8; 4])>),
+    /// An embedded image, positioned by its top left corner relative to the glyph origin.
+    Bitmap(image::RgbaImage, i32, i32),
+}
+
+impl ColorGlyph {
+    fn load<'a>(tables: &ColorTables<'a>, outlines: &OutlineGlyphCollection<'a>, glyph_id: GlyphId, size: Size, location: &Location, units_to_pixels: f32) -> Option<Self> {
+        if let Some(layers) = tables.colr.as_ref().and_then(|colr| colr.v0_base_glyph(glyph_id).ok()?) {
+            let colr = tables.colr.as_ref()?;
+            let layers = layers.filter_map(|index| colr.v0_layer(index).ok()).collect::<Vec<_>>();
+
+            // Palette index 0xFFFF refers to the text color, so glyphs drawn only in the text color are left
+            // to tinting in the shader like any monochrome glyph. Alongside other colors, it is drawn in white.
+            if layers.iter().all(|&(_, palette_index)| palette_index == 0xFFFF) {
+                return None;
+            }
+
+            let layers = layers.into_iter().map(|(layer_glyph, palette_index)| {
+                let outline = GlyphOutline::load(outlines, layer_glyph.into(), DrawSettings::unhinted(size, location));
+                let color = tables.palette.get(palette_index as usize).copied().unwrap_or([0xFF; 4]);
+                (outline, color)
+            });
+
+            return Some(ColorGlyph::Layers(layers.collect()));
+        }
+
+        let bitmap = tables.strikes.as_ref()?.glyph_for_size(size, glyph_id)?;
+        let image = match bitmap.data {
+            BitmapData::Png(data) => image::load_from_memory_with_format(data, image::ImageFormat::Png).ok()?.into_rgba8(),
+            BitmapData::Bgra(data) => image::RgbaImage::from_fn(bitmap.width, bitmap.height, |x, y| {
+                let index = ((y * bitmap.width + x) * 4) as usize;
+                let [b, g, r, a] = data.get(index..index + 4).and_then(|bgra| bgra.try_into().ok()).unwrap_or([0; 4]);
+                let unpremultiply = |c: u8| if a == 0 { 0 } else { (c as u32 * 0xFF / a as u32).min(0xFF) as u8 };
+                image::Rgba([unpremultiply(r), unpremultiply(g), unpremultiply(b), a])
+            }),
+            _ => return None,
+        };
+
+        // Strikes rarely match the requested size exactly, so scale the image to fit
+        let scale = size.ppem()? / bitmap.ppem_y;
+        let width = (image.width() as f32 * scale).round().max(1.0) as u32;
+        let height = (image.height() as f32 * scale).round().max(1.0) as u32;
+        let image = image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle);
+
+        // Outer bearings are given in font units, inner bearings in pixels of the strike
+        let left = bitmap.bearing_x * units_to_pixels + bitmap.inner_bearing_x * scale;
+        let top = match bitmap.placement_origin {
+            Origin::TopLeft => bitmap.bearing_y * units_to_pixels + bitmap.inner_bearing_y * scale,
+            Origin::BottomLeft => bitmap.bearing_y * units_to_pixels + bitmap.inner_bearing_y * scale + height as f32,
+        };
+
+        Some(ColorGlyph::Bitmap(image, left.round() as i32, -top.round() as i32))
+    }
+
+    fn pixel_bounding_box(&self) -> Option<PixelRect> {
+        match self {
+            ColorGlyph::Layers(layers) => layers.iter()
+                .filter_map(|(outline, _)| outline.pixel_bounding_box())
+                .reduce(|a, b| PixelRect {
+                    min_x: a.min_x.min(b.min_x),
+                    min_y: a.min_y.min(b.min_y),
+                    max_x: a.max_x.max(b.max_x),
+                    max_y: a.max_y.max(b.max_y),
+                }),
+            ColorGlyph::Bitmap(image, left, top) => Some(PixelRect {
+                min_x: *left,
+                min_y: *top,
+                max_x: left + image.width() as i32,
+                max_y: top + image.height() as i32,
+            }),
+        }
+    }
+
+    /// Rasterizes the glyph, calling `o` with the coordinates of each pixel relative to the
+    /// [pixel bounding box](ColorGlyph::pixel_bounding_box) and its non-premultiplied color.
+    fn draw(&self, mut o: impl FnMut(u32, u32, [u8; 4])) {
+        let Some(bounding_box) = self.pixel_bounding_box() else {
+            return;
+        };
+
+        match self {
+            ColorGlyph::Layers(layers) => {
+                // Composite layers with premultiplied alpha
+                let width = bounding_box.width() as u32;
+                let mut pixels = vec![[0.0f32; 4]; (width * bounding_box.height() as u32) as usize];
+                for (outline, color) in layers.iter() {
+                    let Some(layer_box) = outline.pixel_bounding_box() else {
+                        continue;
+                    };
+
+                    let offset_x = (layer_box.min_x - bounding_box.min_x) as u32;
+                    let offset_y = (layer_box.min_y - bounding_box.min_y) as u32;
+                    let alpha = color[3] as f32 / 255.0;
+                    outline.draw(|x, y, v| {
+                        let pixel = &mut pixels[((offset_y + y) * width + offset_x + x) as usize];
+                        let source_alpha = alpha * v.clamp(0.0, 1.0);
+                        for channel in 0..3 {
+                            pixel[channel] = color[channel] as f32 / 255.0 * source_alpha + pixel[channel] * (1.0 - source_alpha);
+                        }
+                        pixel[3] = source_alpha + pixel[3] * (1.0 - source_alpha);
+                    });
+                }
+
+                for (index, pixel) in pixels.iter().enumerate() {
+                    let alpha = pixel[3];
+                    let unpremultiply = |c: f32| if alpha > 0.0 { (c / alpha * 255.0).round() as u8 } else { 0 };
+                    let color = [unpremultiply(pixel[0]), unpremultiply(pixel[1]), unpremultiply(pixel[2]), (alpha * 255.0).round() as u8];
+                    o(index as u32 % width, index as u32 / width, color);
+                }
+            },
+            ColorGlyph::Bitmap(image, _, _) => {
+                for (x, y, pixel) in image.enumerate_pixels() {
+                    o(x, y, pixel.0);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kerning_classes_match_kerning_table() {
+        // "A" and "Á" kern alike on either side, "V" and "W" only on the right
+        let kerning_table = HashMap::from([
+            (('A', 'V'), -2.0), (('A', 'W'), -2.0), (('\u{C1}', 'V'), -2.0), (('\u{C1}', 'W'), -2.0),
+            (('V', 'A'), -1.5), (('V', '\u{C1}'), -1.5), (('W', 'A'), -1.0), (('W', '\u{C1}'), -1.0),
+            (('T', 'o'), -3.0),
+        ]);
+
+        let kerning_classes = kerning_classes(&kerning_table, usize::MAX).unwrap();
+        assert_eq!(kerning_classes.right_classes[&'V'], kerning_classes.right_classes[&'W']);
+        assert_ne!(kerning_classes.left_classes[&'V'], kerning_classes.left_classes[&'W']);
+
+        let characters = ['A', '\u{C1}', 'V', 'W', 'T', 'o', 'x'];
+        for left in characters {
+            for right in characters {
+                let expected = kerning_table.get(&(left, right)).copied().unwrap_or(0.0);
+                assert_eq!(kerning_classes.get(left, right), expected, "kerning between {left} and {right}");
+            }
+        }
+    }
+
+    #[test]
+    fn distance_field_decodes_encoded_distances() {
+        for (mapping, edge_value) in [(DistanceMapping::Linear, 127.5), (DistanceMapping::Squared, 127.5), (DistanceMapping::Squared, 191.0)] {
+            let distance_field = DistanceField { outside_range: 8.0, inside_range: 2.0, edge_value, mapping, format: DistanceFormat::U16 };
+            for step in -32..=8 {
+                let distance = step as f32 / 4.0;
+                let value = (encode_distance(&distance_field, distance) * 257.0).round() as u16;
+                let decoded = distance_field.distance_u16(value);
+                assert!((decoded - distance).abs() < 0.05, "{distance} decoded as {decoded} with edge value {edge_value}");
+            }
+
+            // Distances beyond the ranges saturate
+            assert_eq!(encode_distance(&distance_field, -9.0), 0.0);
+            assert_eq!(encode_distance(&distance_field, 3.0), 255.0);
+        }
+    }
+
+    #[test]
+    fn kerning_classes_respect_max_size() {
+        let kerning_table = HashMap::from([(('A', 'V'), -2.0), (('T', 'o'), -3.0)]);
+        assert!(kerning_classes(&kerning_table, 24).is_none());
+        assert!(kerning_classes(&kerning_table, usize::MAX).is_some());
+    }
+
+    #[test]
+    fn compress_bc4_block_layout() {
+        // With extremes of 70 and 0 the interpolated palette is every multiple of 10 from 60 down to 10
+        let image = image::GrayImage::from_raw(5, 2, vec![70, 0, 10, 60, 35, 40, 20, 30, 50, 35]).unwrap();
+        let compressed = compress_bc4(&image, 1);
+        assert_eq!(compressed.len(), 16);
+
+        // The last two rows of the first block repeat the last row of the image
+        let mut indices = [0; 8];
+        indices[..6].copy_from_slice(&compressed[2..8]);
+        let indices = u64::from_le_bytes(indices);
+        let expected = [0, 1, 7, 2, 4, 6, 5, 3, 4, 6, 5, 3, 4, 6, 5, 3];
+        assert_eq!(compressed[..2], [70, 0]);
+        for (pixel, expected) in expected.into_iter().enumerate() {
+            assert_eq!(indices >> (pixel * 3) & 0b111, expected, "index of pixel {pixel}");
+        }
+
+        // The second block only has the repeated last column
+        assert_eq!(compressed[8..], [35, 35, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn compress_bc5_interleaves_channels() {
+        let image = image::GrayAlphaImage::from_raw(1, 1, vec![200, 100]).unwrap();
+        assert_eq!(compress_bc4(&image, 2), [200, 200, 0, 0, 0, 0, 0, 0, 100, 100, 0, 0, 0, 0, 0, 0]);
+    }
+}